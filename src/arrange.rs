@@ -0,0 +1,208 @@
+//! Rearrangement of audio segments laid out on a Hilbert curve.
+//!
+//! Because the Hilbert curve visits every power-of-two sub-square of an image
+//! contiguously, each such sub-square corresponds to one segment of
+//! consecutive samples. An [`Arrangement`] treats these segments like the
+//! tiles of a looper, allowing them to be permuted, duplicated, reversed or
+//! muted.
+
+/// A single segment within an [`Arrangement`].
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Segment {
+    /// The index of the source segment to play.
+    pub source: usize,
+    /// Whether the segment is played backwards.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub reverse: bool,
+    /// Whether the segment is replaced by silence.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mute: bool,
+}
+
+/// A sequence of segments to render from some source audio.
+pub struct Arrangement {
+    segment_size: usize,
+    channels: usize,
+    crossfade: usize,
+    segments: Vec<Segment>,
+}
+
+impl Arrangement {
+    /// Create a new arrangement of segments which each correspond to a square
+    /// of side `segment_side` on the Hilbert curve.
+    ///
+    /// `segment_side` must be a power of two, and each segment must hold a
+    /// whole number of frames of `channels` samples.
+    pub fn new(
+        segment_side: u32,
+        channels: u16,
+        segments: Vec<Segment>,
+    ) -> Self {
+        assert!(segment_side.is_power_of_two());
+        assert!(channels > 0);
+
+        let segment_size = segment_side as usize * segment_side as usize;
+        assert_eq!(segment_size % channels as usize, 0);
+
+        Arrangement {
+            segment_size,
+            channels: channels as usize,
+            crossfade: 0,
+            segments,
+        }
+    }
+
+    /// Crossfade over `crossfade` samples at every segment boundary.
+    ///
+    /// The crossfade is centered on the boundary and blends the continuation of
+    /// the outgoing segment with the lead-in of the incoming one, so the length
+    /// of the output is unchanged. It is clamped to the segment size.
+    pub fn with_crossfade(mut self, crossfade: usize) -> Self {
+        self.crossfade = crossfade.min(self.segment_size);
+        self
+    }
+
+    /// The number of samples in a single segment.
+    pub fn segment_size(&self) -> usize {
+        self.segment_size
+    }
+
+    /// The number of complete segments available in `source`.
+    pub fn source_segments(&self, source: &[i16]) -> usize {
+        source.len() / self.segment_size
+    }
+
+    /// Render the arrangement from the samples in `source`.
+    ///
+    /// Segments whose source lies outside of `source` are rendered as silence.
+    pub fn render(&self, source: &[i16]) -> Vec<i16> {
+        let half = self.crossfade as i64 / 2;
+        let size = self.segment_size as i64;
+
+        let mut output =
+            Vec::with_capacity(self.segments.len() * self.segment_size);
+
+        for (idx, segment) in self.segments.iter().enumerate() {
+            let prev = idx.checked_sub(1).map(|idx| &self.segments[idx]);
+            let next = self.segments.get(idx + 1);
+
+            for t in 0..size {
+                let sample = match (prev, next) {
+                    (Some(prev), _) if t < half => self.blend(
+                        source,
+                        (prev, t + size),
+                        (segment, t),
+                        t + half,
+                    ),
+                    (_, Some(next)) if t >= size - half => self.blend(
+                        source,
+                        (segment, t),
+                        (next, t - size),
+                        t - (size - half),
+                    ),
+                    _ => self.sample_at(source, segment, t),
+                };
+                output.push(sample.round() as i16);
+            }
+        }
+
+        output
+    }
+
+    /// Blend between two segments at `position` within the crossfade.
+    fn blend(
+        &self,
+        source: &[i16],
+        (from, from_t): (&Segment, i64),
+        (to, to_t): (&Segment, i64),
+        position: i64,
+    ) -> f32 {
+        let length = 2 * (self.crossfade / 2);
+        let weight = (position as f32 + 0.5) / length as f32;
+        let from = self.sample_at(source, from, from_t);
+        let to = self.sample_at(source, to, to_t);
+        from + (to - from) * weight
+    }
+
+    /// The sample at offset `t` from the start of `segment`.
+    ///
+    /// Offsets outside of the segment continue into the surrounding source
+    /// audio, in the direction the segment is played.
+    fn sample_at(&self, source: &[i16], segment: &Segment, t: i64) -> f32 {
+        if segment.mute {
+            return 0.;
+        }
+
+        let start = (segment.source * self.segment_size) as i64;
+        let index = if segment.reverse {
+            let channels = self.channels as i64;
+            let frame = t.div_euclid(channels);
+            let channel = t.rem_euclid(channels);
+            let last_frame = (self.segment_size / self.channels) as i64 - 1;
+            start + (last_frame - frame) * channels + channel
+        } else {
+            start + t
+        };
+
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| source.get(index))
+            .map_or(0., |&sample| sample as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(source: usize) -> Segment {
+        Segment {
+            source,
+            ..Segment::default()
+        }
+    }
+
+    #[test]
+    fn segments_are_rearranged() {
+        // two stereo segments of two frames each
+        let source = [1, 2, 3, 4, 5, 6, 7, 8];
+        let segments = vec![
+            segment(1),
+            Segment {
+                reverse: true,
+                ..segment(0)
+            },
+            Segment {
+                mute: true,
+                ..segment(1)
+            },
+            segment(5),
+        ];
+
+        let arrangement = Arrangement::new(2, 2, segments);
+        assert_eq!(arrangement.segment_size(), 4);
+        assert_eq!(arrangement.source_segments(&source), 2);
+        assert_eq!(
+            arrangement.render(&source),
+            [5, 6, 7, 8, 3, 4, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn crossfades_blend_into_the_surrounding_source() {
+        let source = [0, 0, 0, 0, 100, 100, 100, 100];
+        let render = |segments| {
+            Arrangement::new(2, 1, segments)
+                .with_crossfade(2)
+                .render(&source)
+        };
+
+        // segments in their original order continue into each other
+        assert_eq!(render(vec![segment(0), segment(1)]), source);
+        assert_eq!(
+            render(vec![segment(1), segment(0)]),
+            [100, 100, 100, 75, 0, 0, 0, 0]
+        );
+    }
+}
@@ -1,6 +1,8 @@
 use std::{
     fs,
+    io::Write,
     path::{Path, PathBuf},
+    process::{self, Stdio},
 };
 
 use anyhow::{bail, Context};
@@ -18,17 +20,33 @@ use clap::CommandFactory;
 use clap_complete::{generate, Generator, Shell};
 
 use hilly_sounds::{
-    decode_image, encode_image,
-    strategy::{ColorStrategy, SpaceStrategy},
-    Decoder,
+    encode_canvas,
+    resample::Resampler,
+    stego::{self, PixelOrder},
+    strategy::{
+        color::{LsbColorStrategy, PixelFormat},
+        ColorStrategy, SpaceStrategy,
+    },
+    video::{FrameRenderer, FrameStyle},
+    AlphaMode, AlphaSource, Canvas, Decoder, Dither, FillPolicy, SampleConvert,
+    TransparentPixels, Volume,
 };
 
+mod layout;
+use layout::Layout;
+
+mod metadata;
+use metadata::{load_png, read_length, save_png};
+
 mod preset;
-use preset::Preset;
+use preset::{Preset, SpacePreset};
 
 mod util;
 use util::*;
 
+mod volume;
+use volume::{load_volume, save_volume};
+
 #[derive(Parser)]
 #[clap(name = "hscli", version, color = clap::ColorChoice::Never)]
 struct Args {
@@ -55,6 +73,9 @@ enum Command {
         /// extension instead (e.g. example.wav to example.png). This is used
         /// both when no output path is specified and when only a directory is
         /// provided.
+        ///
+        /// Volume space strategies instead write a directory of PNG slices,
+        /// or a multi-page TIFF file if the output path ends in .tif or .tiff.
         #[clap(value_hint = ValueHint::AnyPath)]
         output_path: Option<PathBuf>,
 
@@ -66,11 +87,35 @@ enum Command {
         /// after it has been saved.
         #[clap(short, long)]
         open: bool,
+
+        /// How to fill the rest of the image after the audio ends.
+        #[clap(arg_enum, long, default_value_t = FillMode::Transparent)]
+        fill: FillMode,
+
+        /// Write the amplitude envelope of the audio to the alpha channel,
+        /// decaying over the given number of samples.
+        #[clap(long, value_name = "RELEASE", conflicts_with = "alpha-signal")]
+        envelope: Option<f32>,
+
+        /// Write the samples of a second WAV file to the alpha channel.
+        #[clap(long, value_name = "FILE", validator = validate_is_file, value_hint = ValueHint::FilePath)]
+        alpha_signal: Option<PathBuf>,
+
+        /// Add TPDF dither for a color strategy which keeps the given number
+        /// of bits of each sample, such as 8.
+        #[clap(long, value_name = "BITS", validator = validate_sample_bits)]
+        dither: Option<u32>,
+
+        /// Feed the quantization error of each sample back into the next, to
+        /// move it towards high frequencies.
+        #[clap(long)]
+        noise_shaping: bool,
     },
-    /// Decode a PNG file into a WAV file.
+    /// Decode a PNG file (or a volume) into a WAV file.
     Decode {
-        /// Path to the input PNG file.
-        #[clap(validator = validate_is_file, value_hint = ValueHint::FilePath)]
+        /// Path to the input PNG file, or to the volume for volume space
+        /// strategies.
+        #[clap(validator = validate_exists, value_hint = ValueHint::AnyPath)]
         input_file: PathBuf,
 
         /// Output path for the WAV file, either a file or directory.
@@ -87,8 +132,24 @@ enum Command {
         channels: u16,
 
         /// The sample rate to output to.
-        #[clap(short, long, default_value_t = 48000)]
-        sample_rate: u32,
+        ///
+        /// By default, this is the original sample rate from the preset if it
+        /// has one, or 48000 otherwise.
+        #[clap(short, long)]
+        sample_rate: Option<u32>,
+
+        /// How to decode fully transparent pixels.
+        #[clap(arg_enum, long, default_value_t = TransparentMode::Silence)]
+        transparent: TransparentMode,
+
+        /// How to use the alpha channel of other pixels.
+        #[clap(arg_enum, long, default_value_t = AlphaUse::Ignore)]
+        alpha: AlphaUse,
+
+        /// Write 32-bit float samples, keeping any precision beyond 16 bits
+        /// that the color strategy has.
+        #[clap(long)]
+        float: bool,
     },
     /// Decode a PNG file and play it.
     DecodePlay {
@@ -101,8 +162,11 @@ enum Command {
         channels: u16,
 
         /// The sample rate to output to.
-        #[clap(short, long, default_value_t = 48000)]
-        sample_rate: u32,
+        ///
+        /// By default, this is the original sample rate from the preset if it
+        /// has one, or 48000 otherwise.
+        #[clap(short, long)]
+        sample_rate: Option<u32>,
 
         /// The output audio device with which to play the file.
         #[clap(short, long)]
@@ -111,6 +175,145 @@ enum Command {
         /// List the available output audio devices.
         #[clap(short, long)]
         list_devices: bool,
+
+        /// How to decode fully transparent pixels.
+        #[clap(arg_enum, long, default_value_t = TransparentMode::Silence)]
+        transparent: TransparentMode,
+
+        /// How to use the alpha channel of other pixels.
+        #[clap(arg_enum, long, default_value_t = AlphaUse::Ignore)]
+        alpha: AlphaUse,
+    },
+    /// Rearrange the segments of a PNG file and render the result to a WAV
+    /// file.
+    ///
+    /// Each segment is a power-of-two square along the space strategy's
+    /// curve. The layout file lists the segments to play in order, each of
+    /// which may be reversed or muted.
+    Rearrange {
+        /// Path to the input PNG file.
+        #[clap(validator = validate_is_file, value_hint = ValueHint::FilePath)]
+        input_file: PathBuf,
+
+        /// Path to a TOML layout file describing the new arrangement.
+        #[clap(validator = validate_is_file, value_hint = ValueHint::FilePath)]
+        layout_file: PathBuf,
+
+        /// Output path for the WAV file, either a file or directory.
+        ///
+        /// By default, the file name is the same as the input with the .wav
+        /// extension instead (e.g. example.png to example.wav). This is used
+        /// both when no output path is specified and when only a directory is
+        /// provided.
+        #[clap(value_hint = ValueHint::AnyPath)]
+        output_path: Option<PathBuf>,
+
+        /// The number of channels to output to.
+        #[clap(short, long, default_value_t = 2)]
+        channels: u16,
+
+        /// The sample rate to output to.
+        ///
+        /// By default, this is the original sample rate from the preset if it
+        /// has one, or 48000 otherwise.
+        #[clap(short, long)]
+        sample_rate: Option<u32>,
+    },
+    /// Render a video of a PNG file being played back.
+    ///
+    /// If ffmpeg is available, the frames are piped to it along with the
+    /// decoded audio to produce a video file. Otherwise, the frames are written
+    /// to a directory as a numbered PNG sequence, next to the decoded audio.
+    RenderVideo {
+        /// Path to the input PNG file.
+        #[clap(validator = validate_is_file, value_hint = ValueHint::FilePath)]
+        input_file: PathBuf,
+
+        /// Output path for the video file or frame directory.
+        ///
+        /// By default, this is the input path with the .mp4 extension for
+        /// videos (e.g. example.png to example.mp4), or without an extension
+        /// for frame directories (e.g. example.png to example/).
+        #[clap(value_hint = ValueHint::AnyPath)]
+        output_path: Option<PathBuf>,
+
+        /// The number of frames per second.
+        #[clap(long, default_value_t = 30)]
+        fps: u32,
+
+        /// How pixels which have not been played yet are shown.
+        #[clap(arg_enum, long, default_value_t = VideoStyle::Reveal)]
+        style: VideoStyle,
+
+        /// Write a PNG sequence even if ffmpeg is available.
+        #[clap(long)]
+        frames: bool,
+
+        /// The number of channels to output to.
+        #[clap(short, long, default_value_t = 2)]
+        channels: u16,
+
+        /// The sample rate to output to.
+        ///
+        /// By default, this is the original sample rate from the preset if it
+        /// has one, or 48000 otherwise.
+        #[clap(short, long)]
+        sample_rate: Option<u32>,
+    },
+    /// Hide a WAV file in the least significant bits of a cover PNG file.
+    ///
+    /// The preset is not used; the audio is always hidden with the LSB color
+    /// strategy.
+    Hide {
+        /// Path to the input WAV file.
+        #[clap(validator = validate_is_file, value_hint = ValueHint::FilePath)]
+        input_file: PathBuf,
+
+        /// Path to the cover PNG file.
+        #[clap(validator = validate_is_file, value_hint = ValueHint::FilePath)]
+        cover_file: PathBuf,
+
+        /// Output path for the PNG file, either a file or directory.
+        ///
+        /// By default, the file name is the same as the input with the .png
+        /// extension instead (e.g. example.wav to example.png). This is used
+        /// both when no output path is specified and when only a directory is
+        /// provided.
+        #[clap(value_hint = ValueHint::AnyPath)]
+        output_path: Option<PathBuf>,
+
+        /// Key with which to scramble the order of pixels.
+        ///
+        /// By default, the pixels are visited along a Hilbert curve.
+        #[clap(short, long, env = "HSCLI_KEY")]
+        key: Option<u64>,
+
+        /// The number of bits to hide in each color channel.
+        #[clap(short, long, default_value_t = 4)]
+        bits: u32,
+    },
+    /// Reveal a WAV file hidden in a PNG file with `hide`.
+    Reveal {
+        /// Path to the input PNG file.
+        #[clap(validator = validate_is_file, value_hint = ValueHint::FilePath)]
+        input_file: PathBuf,
+
+        /// Output path for the WAV file, either a file or directory.
+        ///
+        /// By default, the file name is the same as the input with the .wav
+        /// extension instead (e.g. example.png to example.wav). This is used
+        /// both when no output path is specified and when only a directory is
+        /// provided.
+        #[clap(value_hint = ValueHint::AnyPath)]
+        output_path: Option<PathBuf>,
+
+        /// Key with which the order of pixels was scrambled.
+        #[clap(short, long, env = "HSCLI_KEY")]
+        key: Option<u64>,
+
+        /// The number of bits hidden in each color channel.
+        #[clap(short, long, default_value_t = 4)]
+        bits: u32,
     },
     /// Dump the current preset to the standard output.
     DumpPreset {
@@ -130,6 +333,80 @@ enum Command {
     },
 }
 
+#[derive(ArgEnum, Copy, Clone)]
+enum VideoStyle {
+    Reveal,
+    Highlight,
+}
+
+impl From<VideoStyle> for FrameStyle {
+    fn from(style: VideoStyle) -> Self {
+        match style {
+            VideoStyle::Reveal => FrameStyle::Reveal,
+            VideoStyle::Highlight => FrameStyle::Highlight,
+        }
+    }
+}
+
+#[derive(ArgEnum, Copy, Clone)]
+enum FillMode {
+    Transparent,
+    Silence,
+    Loop,
+    Mirror,
+}
+
+impl From<FillMode> for FillPolicy {
+    fn from(mode: FillMode) -> Self {
+        match mode {
+            FillMode::Transparent => FillPolicy::Transparent,
+            FillMode::Silence => FillPolicy::Silence,
+            FillMode::Loop => FillPolicy::Loop,
+            FillMode::Mirror => FillPolicy::Mirror,
+        }
+    }
+}
+
+#[derive(ArgEnum, Copy, Clone)]
+enum TransparentMode {
+    /// Decode transparent pixels as silence.
+    Silence,
+    /// Stop decoding at the first transparent pixel.
+    End,
+    /// Leave transparent pixels out.
+    Skip,
+}
+
+impl From<TransparentMode> for TransparentPixels {
+    fn from(mode: TransparentMode) -> Self {
+        match mode {
+            TransparentMode::Silence => TransparentPixels::Silence,
+            TransparentMode::End => TransparentPixels::EndOfStream,
+            TransparentMode::Skip => TransparentPixels::Skip,
+        }
+    }
+}
+
+#[derive(ArgEnum, Copy, Clone)]
+enum AlphaUse {
+    /// Ignore the alpha channel.
+    Ignore,
+    /// Use the alpha channel as a gain.
+    Gain,
+    /// Decode the second signal in the alpha channel instead of the color.
+    Signal,
+}
+
+impl From<AlphaUse> for AlphaMode {
+    fn from(alpha: AlphaUse) -> Self {
+        match alpha {
+            AlphaUse::Ignore => AlphaMode::Ignore,
+            AlphaUse::Gain => AlphaMode::Gain,
+            AlphaUse::Signal => AlphaMode::Signal,
+        }
+    }
+}
+
 #[derive(ArgEnum, Copy, Clone)]
 enum DumpFormat {
     Debug,
@@ -148,7 +425,7 @@ fn main() -> anyhow::Result<()> {
     }
 
     // TODO handle validation errors
-    let preset = if let Some(preset_path) = args.preset_path {
+    let mut preset = if let Some(preset_path) = args.preset_path {
         let preset_toml = fs::read_to_string(preset_path)
             .context("failed to read preset file")?;
         toml::from_str(&preset_toml)
@@ -157,8 +434,57 @@ fn main() -> anyhow::Result<()> {
         Preset::default()
     };
 
-    let (color_strategy, space_strategy) =
-        (preset.color.to_strategy(), preset.space.to_strategy());
+    let encoded_sample_rate = preset
+        .space
+        .encoded_sample_rate()
+        .context("failed to create space strategy")?;
+
+    // the number of samples in the input audio, or recorded in the input image
+    let length = match &args.command {
+        Command::Encode { input_file, .. } => Some(
+            input_length(input_file, encoded_sample_rate)
+                .context("failed to read input length")?,
+        ),
+        Command::Decode { input_file, .. }
+        | Command::DecodePlay { input_file, .. }
+        | Command::Rearrange { input_file, .. }
+        | Command::RenderVideo { input_file, .. } => {
+            let length = read_length(input_file)
+                .context("failed to read recorded length")?;
+            if length.is_none() && preset.space.is_auto() {
+                bail!("the input has no recorded length to fit the size to");
+            }
+            length
+        }
+        _ => None,
+    };
+    let color_strategy = preset
+        .color
+        .to_strategy()
+        .context("failed to create color strategy")?;
+    let samples_per_pixel = color_strategy.samples_per_pixel();
+    if let Some(length) = length {
+        preset.space.fit(length.div_ceil(samples_per_pixel));
+    }
+
+    let pixel_format = color_strategy.pixel_format();
+    let space_strategy = || {
+        preset
+            .space
+            .to_strategy()
+            .context("failed to create space strategy")
+    };
+    let volume_strategy = || {
+        preset
+            .space
+            .to_volume_strategy()
+            .context("failed to create space strategy")
+    };
+    let output_sample_rate = |sample_rate: &Option<u32>| {
+        sample_rate
+            .or_else(|| preset.space.original_sample_rate())
+            .unwrap_or(48000)
+    };
 
     match &args.command {
         Command::Encode {
@@ -166,40 +492,159 @@ fn main() -> anyhow::Result<()> {
             output_path,
             skip,
             open,
+            fill,
+            envelope,
+            alpha_signal,
+            dither,
+            noise_shaping,
         } => {
-            let output_file =
-                resolve_output_file(input_file, output_path, "png");
-            encode(
-                input_file,
-                &output_file,
-                *skip,
-                *open,
-                color_strategy,
-                space_strategy,
-            )
-            .context("failed to run encoder")?;
+            if let Some(channels) = preset.space.channels() {
+                let input_channels =
+                    WavReader::open(input_file)?.spec().channels;
+                if input_channels != channels {
+                    bail!(
+                        "the preset is laid out for {} channels, but the input has {}",
+                        channels,
+                        input_channels
+                    );
+                }
+            }
+
+            let alpha = || -> anyhow::Result<AlphaSource> {
+                Ok(match (envelope, alpha_signal) {
+                    (Some(release), _) => {
+                        AlphaSource::Envelope { release: *release }
+                    }
+                    (None, Some(alpha_file)) => {
+                        let mut reader = WavReader::open(alpha_file)?;
+                        let samples = read_samples(&mut reader)?;
+                        AlphaSource::Signal(Box::new(samples.into_iter()))
+                    }
+                    (None, None) => AlphaSource::Opaque,
+                })
+            };
+
+            if *noise_shaping && samples_per_pixel != 1 {
+                bail!("noise shaping is not supported by color strategies which pack samples");
+            }
+            if *noise_shaping && color_strategy.context() > 0 {
+                bail!("noise shaping is not supported by color strategies which look at neighboring samples");
+            }
+            let dither = Dither {
+                step: dither.map_or(0., |bits| 2f32.powi(16 - bits as i32)),
+                noise_shaping: *noise_shaping,
+            };
+
+            // the number of samples which actually fit after skipping, where
+            // looping or mirroring fills the entire space with audio
+            let fitted_length = |size: usize| {
+                let size = size * samples_per_pixel;
+                length.map(|length| {
+                    let length = length.saturating_sub(skip * size).min(size);
+                    match fill {
+                        FillMode::Loop | FillMode::Mirror if length > 0 => size,
+                        _ => length,
+                    }
+                })
+            };
+
+            if preset.space.is_volume() {
+                let volume_strategy = volume_strategy()?;
+                let output_path =
+                    resolve_output_file(input_file, output_path, "");
+                let length = fitted_length(volume_strategy.size());
+                let volume: Volume = encode(
+                    input_file,
+                    *skip,
+                    encoded_sample_rate,
+                    (*fill).into(),
+                    alpha()?,
+                    dither,
+                    color_strategy,
+                    volume_strategy,
+                )
+                .context("failed to run encoder")?;
+                save_volume(&volume, &output_path, length, pixel_format)
+                    .context("failed to save volume")?;
+            } else {
+                let output_file =
+                    resolve_output_file(input_file, output_path, "png");
+                let space_strategy = space_strategy()?;
+                let length = fitted_length(space_strategy.size());
+                let image: image::RgbaImage = encode(
+                    input_file,
+                    *skip,
+                    encoded_sample_rate,
+                    (*fill).into(),
+                    alpha()?,
+                    dither,
+                    color_strategy,
+                    space_strategy,
+                )
+                .context("failed to run encoder")?;
+                save_png(&image, &output_file, length, pixel_format)
+                    .context("failed to save PNG")?;
+
+                if *open {
+                    opener::open(&output_file)?;
+                }
+            }
         }
         Command::Decode {
             input_file,
             output_path,
             channels,
             sample_rate,
+            transparent,
+            alpha,
+            float,
         } => {
             let output_file =
                 resolve_output_file(input_file, output_path, "wav");
             let wav_spec = WavSpec {
                 channels: *channels,
-                sample_rate: *sample_rate,
-                bits_per_sample: 16,
-                sample_format: hound::SampleFormat::Int,
+                sample_rate: output_sample_rate(sample_rate),
+                bits_per_sample: if *float { 32 } else { 16 },
+                sample_format: if *float {
+                    hound::SampleFormat::Float
+                } else {
+                    hound::SampleFormat::Int
+                },
             };
-            decode(
-                input_file,
-                &output_file,
-                wav_spec,
-                color_strategy,
-                space_strategy,
-            )
+            if preset.space.is_volume() {
+                let volume_strategy = volume_strategy()?;
+                let volume = load_volume(input_file, pixel_format)
+                    .context("failed to load volume")?;
+                decode(
+                    decoder(
+                        volume,
+                        length,
+                        *channels,
+                        (*transparent).into(),
+                        (*alpha).into(),
+                        color_strategy,
+                        volume_strategy,
+                    ),
+                    &output_file,
+                    wav_spec,
+                    encoded_sample_rate,
+                )
+            } else {
+                decode(
+                    decoder(
+                        load_png(input_file, pixel_format)?,
+                        length,
+                        *channels,
+                        (*transparent).into(),
+                        (*alpha).into(),
+                        color_strategy,
+                        space_strategy()?,
+                    ),
+                    &output_file,
+                    wav_spec,
+                    encoded_sample_rate,
+                )
+            }
             .context("failed to run deocder")?;
         }
         Command::DecodePlay {
@@ -208,6 +653,8 @@ fn main() -> anyhow::Result<()> {
             sample_rate,
             device,
             list_devices,
+            transparent,
+            alpha,
         } => {
             let host = cpal::default_host();
 
@@ -232,19 +679,146 @@ fn main() -> anyhow::Result<()> {
                     .context("failed to fo find default output config")?;
                 let config = cpal::StreamConfig {
                     channels: *channels,
-                    sample_rate: cpal::SampleRate(*sample_rate),
+                    sample_rate: cpal::SampleRate(output_sample_rate(
+                        sample_rate,
+                    )),
                     buffer_size: cpal::BufferSize::Default,
                 };
 
+                let image = load_png(input_file, pixel_format)?;
                 decode_play(
-                    input_file,
+                    decoder(
+                        image,
+                        length,
+                        *channels,
+                        (*transparent).into(),
+                        (*alpha).into(),
+                        color_strategy,
+                        space_strategy()?,
+                    ),
                     &device,
                     &config,
-                    color_strategy,
-                    space_strategy,
+                    encoded_sample_rate,
                 )?;
             }
         }
+        Command::Rearrange {
+            input_file,
+            layout_file,
+            output_path,
+            channels,
+            sample_rate,
+        } => {
+            // only the Hilbert curve visits each segment's square in one run
+            if !matches!(preset.space, SpacePreset::Hilbert { .. }) {
+                bail!("segments can only be rearranged on a Hilbert curve");
+            }
+            let layout_toml = fs::read_to_string(layout_file)
+                .context("failed to read layout file")?;
+            let layout = toml::from_str(&layout_toml)
+                .context("failed to parse TOML in layout file")?;
+            let output_file =
+                resolve_output_file(input_file, output_path, "wav");
+            let wav_spec = WavSpec {
+                channels: *channels,
+                sample_rate: output_sample_rate(sample_rate),
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            rearrange(
+                decoder(
+                    load_png(input_file, pixel_format)?,
+                    length,
+                    *channels,
+                    TransparentPixels::default(),
+                    AlphaMode::default(),
+                    color_strategy,
+                    space_strategy()?,
+                ),
+                &output_file,
+                &layout,
+                wav_spec,
+                encoded_sample_rate,
+            )
+            .context("failed to rearrange segments")?;
+        }
+        Command::RenderVideo {
+            input_file,
+            output_path,
+            fps,
+            style,
+            frames,
+            channels,
+            sample_rate,
+        } => {
+            let wav_spec = WavSpec {
+                channels: *channels,
+                sample_rate: output_sample_rate(sample_rate),
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            let use_ffmpeg = !*frames && ffmpeg_available();
+            let output_path = match output_path {
+                Some(path) => path.clone(),
+                None if use_ffmpeg => input_file.with_extension("mp4"),
+                None => input_file.with_extension(""),
+            };
+            let image = load_png(input_file, pixel_format)?;
+            let frame_renderer = FrameRenderer::new(
+                image.clone(),
+                space_strategy()?,
+                *channels,
+                encoded_sample_rate
+                    .map_or(wav_spec.sample_rate, |rate| rate.round() as u32),
+                *fps,
+                (*style).into(),
+            )
+            .with_samples_per_pixel(samples_per_pixel);
+            let frame_renderer = match length {
+                Some(length) => frame_renderer.with_length(length),
+                None => frame_renderer,
+            };
+            render_video(
+                decoder(
+                    image,
+                    length,
+                    *channels,
+                    TransparentPixels::default(),
+                    AlphaMode::default(),
+                    color_strategy,
+                    space_strategy()?,
+                ),
+                &output_path,
+                use_ffmpeg,
+                frame_renderer,
+                wav_spec,
+                encoded_sample_rate,
+            )
+            .context("failed to render video")?;
+        }
+        Command::Hide {
+            input_file,
+            cover_file,
+            output_path,
+            key,
+            bits,
+        } => {
+            let output_file =
+                resolve_output_file(input_file, output_path, "png");
+            hide(input_file, cover_file, &output_file, *key, *bits)
+                .context("failed to hide audio")?;
+        }
+        Command::Reveal {
+            input_file,
+            output_path,
+            key,
+            bits,
+        } => {
+            let output_file =
+                resolve_output_file(input_file, output_path, "wav");
+            reveal(input_file, &output_file, *key, *bits)
+                .context("failed to reveal audio")?;
+        }
         Command::DumpPreset { format, pretty } => {
             dump_preset(&preset, *format, *pretty)
                 .context("failed to dump preset")?;
@@ -266,7 +840,7 @@ fn resolve_output_file(
     output_path: &Option<PathBuf>,
     extension: &str,
 ) -> PathBuf {
-    assert!(input_file.is_file());
+    assert!(input_file.exists());
 
     match output_path {
         Some(dir) if dir.is_dir() => {
@@ -279,34 +853,89 @@ fn resolve_output_file(
     }
 }
 
-fn encode(
+/// The number of samples in a WAV file, after resampling to `sample_rate`.
+fn input_length(
+    input_file: &Path,
+    sample_rate: Option<f64>,
+) -> anyhow::Result<usize> {
+    let reader = WavReader::open(input_file)?;
+    let length = reader.len() as u64;
+
+    Ok(match sample_rate {
+        Some(rate) => length as f64 * rate / reader.spec().sample_rate as f64,
+        None => length as f64,
+    } as usize)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn encode<C, const N: usize>(
     input_file: &Path,
-    output_file: &Path,
     skip: usize,
-    open: bool,
+    sample_rate: Option<f64>,
+    fill: FillPolicy,
+    alpha: AlphaSource,
+    dither: Dither,
     color_strategy: Box<dyn ColorStrategy + Send>,
-    space_strategy: Box<dyn SpaceStrategy<2> + Send>,
-) -> anyhow::Result<()> {
+    space_strategy: Box<dyn SpaceStrategy<N> + Send>,
+) -> anyhow::Result<C>
+where
+    C: Canvas<N>,
+{
     let mut reader = WavReader::open(input_file)?;
+    let spec = reader.spec();
 
-    let skip = skip * space_strategy.size();
-    let image = match reader.spec().sample_format {
-        hound::SampleFormat::Float => match reader.spec().bits_per_sample {
-            16 => encode_image(
-                reader.samples::<f32>().skip(skip).map_while(Result::ok),
+    let skip =
+        skip * space_strategy.size() * color_strategy.samples_per_pixel();
+    let canvas = match spec.sample_format {
+        hound::SampleFormat::Float => match spec.bits_per_sample {
+            32 => encode_samples(
+                reader.samples::<f32>().map_while(Result::ok),
+                &spec,
+                skip,
+                sample_rate,
+                fill,
+                alpha,
+                dither,
                 color_strategy,
                 space_strategy,
             ),
             bps => bail!("unsupported number of bits per sample: {}", bps),
         },
-        hound::SampleFormat::Int => match reader.spec().bits_per_sample {
-            16 => encode_image(
-                reader.samples::<i16>().skip(skip).map_while(Result::ok),
+        hound::SampleFormat::Int => match spec.bits_per_sample {
+            16 => encode_samples(
+                reader.samples::<i16>().map_while(Result::ok),
+                &spec,
+                skip,
+                sample_rate,
+                fill,
+                alpha,
+                dither,
+                color_strategy,
+                space_strategy,
+            ),
+            // scale 24-bit samples up to the range of 32-bit samples
+            24 => encode_samples(
+                reader
+                    .samples::<i32>()
+                    .map_while(Result::ok)
+                    .map(|s| s << 8),
+                &spec,
+                skip,
+                sample_rate,
+                fill,
+                alpha,
+                dither,
                 color_strategy,
                 space_strategy,
             ),
-            32 => encode_image(
-                reader.samples::<i32>().skip(skip).map_while(Result::ok),
+            32 => encode_samples(
+                reader.samples::<i32>().map_while(Result::ok),
+                &spec,
+                skip,
+                sample_rate,
+                fill,
+                alpha,
+                dither,
                 color_strategy,
                 space_strategy,
             ),
@@ -314,42 +943,174 @@ fn encode(
         },
     };
 
-    image
-        .save_with_format(output_file, ImageFormat::Png)
-        .expect("could not save PNG");
+    Ok(canvas)
+}
 
-    if open {
-        opener::open(output_file)?;
+/// Encode samples into an image, first resampling them to `sample_rate` if
+/// it differs from the rate in `spec`.
+#[allow(clippy::too_many_arguments)]
+fn encode_samples<S, I, C, const N: usize>(
+    iter: I,
+    spec: &WavSpec,
+    skip: usize,
+    sample_rate: Option<f64>,
+    fill: FillPolicy,
+    alpha: AlphaSource,
+    dither: Dither,
+    color_strategy: Box<dyn ColorStrategy + Send>,
+    space_strategy: Box<dyn SpaceStrategy<N> + Send>,
+) -> C
+where
+    S: hound::Sample + SampleConvert,
+    I: Iterator<Item = S>,
+    C: Canvas<N>,
+{
+    match sample_rate {
+        Some(rate) if rate != spec.sample_rate as f64 => encode_canvas(
+            Resampler::with_ratio(
+                iter.map(S::convert_to_f32),
+                spec.channels,
+                spec.sample_rate as f64 / rate,
+            )
+            .skip(skip),
+            spec.channels,
+            fill,
+            alpha,
+            dither,
+            color_strategy,
+            space_strategy,
+        ),
+        _ => encode_canvas(
+            iter.skip(skip),
+            spec.channels,
+            fill,
+            alpha,
+            dither,
+            color_strategy,
+            space_strategy,
+        ),
     }
+}
 
-    Ok(())
+/// Create a decoder for a canvas of `channels` interleaved channels, trimmed
+/// to `length` samples if given.
+fn decoder<C, const N: usize>(
+    canvas: C,
+    length: Option<usize>,
+    channels: u16,
+    transparent: TransparentPixels,
+    alpha: AlphaMode,
+    color_strategy: Box<dyn ColorStrategy + Send>,
+    space_strategy: Box<dyn SpaceStrategy<N> + Send>,
+) -> Decoder<C, N>
+where
+    C: Canvas<N>,
+{
+    let decoder = Decoder::new(canvas, color_strategy, space_strategy)
+        .with_channels(channels)
+        .with_transparent(transparent)
+        .with_alpha(alpha);
+    match length {
+        Some(length) => decoder.with_length(length),
+        None => decoder,
+    }
 }
 
-fn decode(
-    input_file: &Path,
+/// Decode samples from an image, resampling them from `sample_rate` to
+/// `output_rate` if the two differ.
+fn decode_samples<C, const N: usize>(
+    decoder: Decoder<C, N>,
+    channels: u16,
+    sample_rate: Option<f64>,
+    output_rate: u32,
+) -> Box<dyn Iterator<Item = i16> + Send>
+where
+    C: Canvas<N> + Send + 'static,
+{
+    resample_samples(decoder, channels, sample_rate, output_rate)
+}
+
+/// Resample decoded samples from `sample_rate` to `output_rate` if the two
+/// differ.
+fn resample_samples<I>(
+    iter: I,
+    channels: u16,
+    sample_rate: Option<f64>,
+    output_rate: u32,
+) -> Box<dyn Iterator<Item = i16> + Send>
+where
+    I: Iterator<Item = i16> + Send + 'static,
+{
+    match sample_rate {
+        Some(rate) if rate != output_rate as f64 => Box::new(
+            Resampler::with_ratio(
+                iter.map(i16::convert_to_f32),
+                channels,
+                rate / output_rate as f64,
+            )
+            .map(f32::convert_to_i16),
+        ),
+        _ => Box::new(iter),
+    }
+}
+
+/// Decode samples from an image into a WAV file, as floats if `wav_spec` has
+/// the float sample format.
+fn decode<C, const N: usize>(
+    mut decoder: Decoder<C, N>,
     output_file: &Path,
     wav_spec: WavSpec,
-    color_strategy: Box<dyn ColorStrategy + Send>,
-    space_strategy: Box<dyn SpaceStrategy<2> + Send>,
-) -> anyhow::Result<()> {
-    let image = image::io::Reader::open(input_file)?.decode()?.to_rgba8();
+    sample_rate: Option<f64>,
+) -> anyhow::Result<()>
+where
+    C: Canvas<N> + Send + 'static,
+{
     let mut writer = WavWriter::create(output_file, wav_spec)?;
-    decode_image(image, &mut writer, color_strategy, space_strategy)?;
+    if wav_spec.sample_format == hound::SampleFormat::Float {
+        let samples = std::iter::from_fn(|| decoder.next_f32());
+        let samples: Box<dyn Iterator<Item = f32>> = match sample_rate {
+            Some(rate) if rate != wav_spec.sample_rate as f64 => {
+                Box::new(Resampler::with_ratio(
+                    samples,
+                    wav_spec.channels,
+                    rate / wav_spec.sample_rate as f64,
+                ))
+            }
+            _ => Box::new(samples),
+        };
+        for sample in samples {
+            writer.write_sample(sample)?;
+        }
+        writer.finalize()?;
+
+        return Ok(());
+    }
+
+    for sample in decode_samples(
+        decoder,
+        wav_spec.channels,
+        sample_rate,
+        wav_spec.sample_rate,
+    ) {
+        writer.write_sample(sample)?;
+    }
     writer.finalize()?;
 
     Ok(())
 }
 
 fn decode_play(
-    input_file: &Path,
+    decoder: Decoder,
     device: &Device,
     config: &cpal::StreamConfig,
-    color_strategy: Box<dyn ColorStrategy + Send>,
-    space_strategy: Box<dyn SpaceStrategy<2> + Send>,
+    sample_rate: Option<f64>,
 ) -> anyhow::Result<()> {
-    let image = image::io::Reader::open(input_file)?.decode()?.to_rgba8();
-
-    let mut decoder = Decoder::new(image, color_strategy, space_strategy);
+    let mut decoder = decode_samples(
+        decoder,
+        config.channels,
+        sample_rate,
+        config.sample_rate.0,
+    );
 
     let err_fn = |err| eprintln!("an error occurred while streaming: {}", err);
 
@@ -370,6 +1131,218 @@ fn decode_play(
     Ok(())
 }
 
+fn rearrange(
+    decoder: Decoder,
+    output_file: &Path,
+    layout: &Layout,
+    wav_spec: WavSpec,
+    sample_rate: Option<f64>,
+) -> anyhow::Result<()> {
+    let arrangement = layout.to_arrangement(wav_spec.channels)?;
+    let source: Vec<i16> = decoder.collect();
+
+    let segments = arrangement.source_segments(&source);
+    if let Some(segment) = layout.segments.iter().find(|s| s.source >= segments)
+    {
+        bail!(
+            "segment {} is out of range (the image has {} segments)",
+            segment.source,
+            segments
+        );
+    }
+
+    let samples = arrangement.render(&source);
+
+    let mut writer = WavWriter::create(output_file, wav_spec)?;
+    for sample in resample_samples(
+        samples.into_iter(),
+        wav_spec.channels,
+        sample_rate,
+        wav_spec.sample_rate,
+    ) {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+
+    Ok(())
+}
+
+fn ffmpeg_available() -> bool {
+    process::Command::new("ffmpeg")
+        .arg("-version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or_default()
+}
+
+/// A temporary file which is removed once dropped, including on errors.
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn render_video(
+    decoder: Decoder,
+    output_path: &Path,
+    use_ffmpeg: bool,
+    frame_renderer: FrameRenderer,
+    wav_spec: WavSpec,
+    sample_rate: Option<f64>,
+) -> anyhow::Result<()> {
+    let (width, height) = frame_renderer.dimensions();
+
+    let audio_file = if use_ffmpeg {
+        std::env::temp_dir()
+            .join(format!("hscli-render-video-{}.wav", process::id()))
+    } else {
+        fs::create_dir_all(output_path)?;
+        output_path.join("audio.wav")
+    };
+    let _temp_file = use_ffmpeg.then(|| TempFile(audio_file.clone()));
+    decode(decoder, &audio_file, wav_spec, sample_rate)?;
+
+    if use_ffmpeg {
+        let mut ffmpeg = process::Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-s", &format!("{}x{}", width, height)])
+            .args(["-framerate", &frame_renderer.fps().to_string()])
+            .args(["-i", "-", "-i"])
+            .arg(&audio_file)
+            // yuv420p needs even dimensions, so pad odd ones with a black edge
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+            .args(["-c:v", "libx264", "-pix_fmt", "yuv420p", "-c:a", "aac"])
+            .arg(output_path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("failed to start ffmpeg")?;
+
+        let mut stdin = ffmpeg.stdin.take().unwrap();
+        for frame in frame_renderer {
+            stdin.write_all(frame.as_raw())?;
+        }
+        drop(stdin);
+
+        let status = ffmpeg.wait()?;
+        if !status.success() {
+            bail!("ffmpeg exited unsuccessfully: {}", status);
+        }
+    } else {
+        for (idx, frame) in frame_renderer.enumerate() {
+            frame.save_with_format(
+                output_path.join(format!("{:06}.png", idx)),
+                ImageFormat::Png,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn lsb_strategy(
+    key: Option<u64>,
+    bits: u32,
+) -> anyhow::Result<(LsbColorStrategy, PixelOrder)> {
+    if !(1..=8).contains(&bits) {
+        bail!("bits per channel must be between 1 and 8: {}", bits);
+    }
+
+    let order = match key {
+        Some(key) => PixelOrder::Keyed(key),
+        None => PixelOrder::Hilbert,
+    };
+
+    Ok((LsbColorStrategy::new(bits), order))
+}
+
+fn read_samples<R: std::io::Read>(
+    reader: &mut WavReader<R>,
+) -> anyhow::Result<Vec<i16>> {
+    let spec = reader.spec();
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => match spec.bits_per_sample {
+            32 => reader
+                .samples::<f32>()
+                .map(|sample| sample.map(f32::convert_to_i16))
+                .collect::<Result<_, _>>()?,
+            bps => bail!("unsupported number of bits per sample: {}", bps),
+        },
+        hound::SampleFormat::Int => match spec.bits_per_sample {
+            16 => reader.samples::<i16>().collect::<Result<_, _>>()?,
+            24 => reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| (sample >> 8) as i16))
+                .collect::<Result<_, _>>()?,
+            32 => reader
+                .samples::<i32>()
+                .map(|sample| sample.map(i32::convert_to_i16))
+                .collect::<Result<_, _>>()?,
+            bps => bail!("unsupported number of bits per sample: {}", bps),
+        },
+    };
+
+    Ok(samples)
+}
+
+fn hide(
+    input_file: &Path,
+    cover_file: &Path,
+    output_file: &Path,
+    key: Option<u64>,
+    bits: u32,
+) -> anyhow::Result<()> {
+    let (strategy, order) = lsb_strategy(key, bits)?;
+
+    let mut reader = WavReader::open(input_file)?;
+    let spec = reader.spec();
+    let samples = read_samples(&mut reader)?;
+
+    let mut cover = load_png(cover_file, PixelFormat::Rgba8)?;
+    stego::hide(
+        &mut cover,
+        &samples,
+        spec.sample_rate,
+        spec.channels,
+        &strategy,
+        order,
+    )?;
+
+    cover.save_with_format(output_file, ImageFormat::Png)?;
+
+    Ok(())
+}
+
+fn reveal(
+    input_file: &Path,
+    output_file: &Path,
+    key: Option<u64>,
+    bits: u32,
+) -> anyhow::Result<()> {
+    let (strategy, order) = lsb_strategy(key, bits)?;
+
+    let image = load_png(input_file, PixelFormat::Rgba8)?;
+    let (header, samples) = stego::reveal(&image, &strategy, order)?;
+
+    let wav_spec = WavSpec {
+        channels: header.channels,
+        sample_rate: header.sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(output_file, wav_spec)?;
+    for sample in samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+
+    Ok(())
+}
+
 fn dump_preset(
     preset: &Preset,
     format: DumpFormat,
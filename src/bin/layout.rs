@@ -0,0 +1,45 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use hilly_sounds::arrange::{Arrangement, Segment};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Layout {
+    /// The side length of each segment's square, which must be a power of
+    /// two.
+    pub segment_side: u32,
+    /// The number of samples to crossfade over at each segment boundary.
+    #[serde(default)]
+    pub crossfade: usize,
+    pub segments: Vec<Segment>,
+}
+
+impl Layout {
+    pub fn to_arrangement(&self, channels: u16) -> anyhow::Result<Arrangement> {
+        if !self.segment_side.is_power_of_two() {
+            bail!("segment side must be a power of two: {}", self.segment_side);
+        }
+        if channels == 0 {
+            bail!("there must be at least one channel");
+        }
+        // segments hold a power of two samples, which only a power of two
+        // channels divides into whole frames
+        let samples = self.segment_side as u64 * self.segment_side as u64;
+        if !channels.is_power_of_two() || channels as u64 > samples {
+            bail!(
+                "segments of {} samples do not divide into frames of {} channels",
+                samples,
+                channels
+            );
+        }
+
+        Ok(
+            Arrangement::new(
+                self.segment_side,
+                channels,
+                self.segments.clone(),
+            )
+            .with_crossfade(self.crossfade),
+        )
+    }
+}
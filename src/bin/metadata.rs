@@ -0,0 +1,139 @@
+//! Saving and loading of encoded images, along with the length of the audio
+//! recorded in them, so that the padding after it can be trimmed when decoding.
+
+use std::{fs, io::BufWriter, path::Path};
+
+use hilly_sounds::strategy::color::{GreyColorStrategy, PixelFormat};
+use nannou::image::{self, RgbaImage};
+
+use crate::volume;
+
+/// The keyword of the PNG text chunk holding the number of encoded samples.
+pub const LENGTH_KEY: &str = "hilly-sounds:length";
+
+/// Save an image as a PNG file in `format`, recording the number of samples
+/// it holds if given.
+pub fn save_png(
+    image: &RgbaImage,
+    path: &Path,
+    length: Option<usize>,
+    format: PixelFormat,
+) -> anyhow::Result<()> {
+    match format {
+        PixelFormat::Rgba8 => write_png(
+            image,
+            path,
+            png::ColorType::RGBA,
+            png::BitDepth::Eight,
+            image.as_raw(),
+            length,
+        ),
+        PixelFormat::LumaA16 => {
+            let data = image
+                .pixels()
+                .flat_map(|color| GreyColorStrategy::to_luma16(color).0)
+                .flat_map(u16::to_be_bytes)
+                .collect::<Vec<_>>();
+            write_png(
+                image,
+                path,
+                png::ColorType::GrayscaleAlpha,
+                png::BitDepth::Sixteen,
+                &data,
+                length,
+            )
+        }
+    }
+}
+
+fn write_png(
+    image: &RgbaImage,
+    path: &Path,
+    color: png::ColorType,
+    depth: png::BitDepth,
+    data: &[u8],
+    length: Option<usize>,
+) -> anyhow::Result<()> {
+    let file = BufWriter::new(fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, image.width(), image.height());
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+
+    let mut writer = encoder.write_header()?;
+    if let Some(length) = length {
+        let text = format!("{}\0{}", LENGTH_KEY, length);
+        writer.write_chunk(*b"tEXt", text.as_bytes())?;
+    }
+    writer.write_image_data(data)?;
+
+    Ok(())
+}
+
+/// Load an image in `format`, as saved by [`save_png`].
+///
+/// Every image is loaded through here, so that images in any format keep all
+/// of their precision.
+pub fn load_png(path: &Path, format: PixelFormat) -> anyhow::Result<RgbaImage> {
+    let image = image::io::Reader::open(path)?.decode()?;
+    match format {
+        PixelFormat::Rgba8 => Ok(image.to_rgba8()),
+        PixelFormat::LumaA16 => {
+            let luma = image.to_luma_alpha16();
+            Ok(RgbaImage::from_fn(luma.width(), luma.height(), |x, y| {
+                GreyColorStrategy::from_luma16(luma.get_pixel(x, y))
+            }))
+        }
+    }
+}
+
+/// Read the number of samples recorded in a PNG file by [`save_png`].
+pub fn read_png_length(path: &Path) -> anyhow::Result<Option<usize>> {
+    let data = fs::read(path)?;
+
+    // walk the chunks after the signature until the image data starts, since
+    // text chunks written by `save_png` always come before it
+    let mut offset = 8;
+    while let Some(header) = data.get(offset..offset + 8) {
+        let chunk_length =
+            u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let chunk_type = &header[4..];
+        let chunk = match data.get(offset + 8..offset + 8 + chunk_length) {
+            Some(chunk) => chunk,
+            None => break,
+        };
+
+        match chunk_type {
+            b"IDAT" | b"IEND" => break,
+            b"tEXt" => {
+                if let Some(length) = chunk
+                    .strip_prefix(LENGTH_KEY.as_bytes())
+                    .and_then(|rest| rest.strip_prefix(b"\0"))
+                    .and_then(|value| std::str::from_utf8(value).ok())
+                    .and_then(|value| value.parse().ok())
+                {
+                    return Ok(Some(length));
+                }
+            }
+            _ => {}
+        }
+
+        // skip the length, type, data and CRC
+        offset += 12 + chunk_length;
+    }
+
+    Ok(None)
+}
+
+/// Read the number of samples recorded in an encoded PNG file or volume.
+pub fn read_length(path: &Path) -> anyhow::Result<Option<usize>> {
+    if volume::is_tiff(path) {
+        volume::read_tiff_length(path)
+    } else if path.is_dir() {
+        match volume::slice_paths(path)?.first() {
+            Some(slice) => read_png_length(slice),
+            None => Ok(None),
+        }
+    } else {
+        read_png_length(path)
+    }
+}
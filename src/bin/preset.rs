@@ -1,8 +1,21 @@
+use anyhow::bail;
 use serde::{Deserialize, Serialize};
 
 use hilly_sounds::strategy::{
-    color::HueColorStrategy,
-    space::{HilbertSpaceStrategy, LineSpaceStrategy, SpaceStrategyAdapter},
+    color::{
+        AnalyticColorStrategy, CachedColorStrategy, Chroma, Colormap,
+        ColormapColorStrategy, CompandedColorStrategy, Companding,
+        GreyColorStrategy, HueColorStrategy, InverseTable,
+        LightnessColorStrategy, LightnessSpace, PackedColorStrategy,
+    },
+    space::{
+        GosperSpaceStrategy, HilbertSpaceStrategy, HilbertVolumeSpaceStrategy,
+        LineSpaceStrategy, MooreSpaceStrategy, PeanoSpaceStrategy,
+        PermutedSpaceStrategy, RasterSpaceStrategy, RingSpaceStrategy,
+        SliceSpaceStrategy, SpaceStrategyAdapter, SpiralSpaceStrategy,
+        TempoSpaceStrategy, TiledSpaceStrategy, Transform,
+        TransformedSpaceStrategy, TransposeSpaceStrategy,
+    },
     ColorStrategy, SpaceStrategy,
 };
 
@@ -19,6 +32,39 @@ pub enum ColorPreset {
         #[serde(flatten)]
         options: HueColorPreset,
     },
+    Colormap {
+        #[serde(flatten)]
+        options: ColormapColorPreset,
+    },
+    Lightness {
+        #[serde(flatten)]
+        options: LightnessColorPreset,
+    },
+    Grey {
+        #[serde(flatten)]
+        options: GreyColorPreset,
+    },
+    /// Pack 2 samples at 16 bits or 4 samples at 8 bits into each pixel.
+    ///
+    /// The 8-bit samples are quantized linearly, so wrap this preset in
+    /// `Companded` to keep the detail of quiet audio.
+    Packed { samples: usize },
+    /// The instantaneous amplitude and frequency of the audio, from a Hilbert
+    /// transform over `taps` samples on either side of each sample.
+    Analytic { taps: usize },
+    /// Compand samples with the given `law` before mapping them to colors
+    /// with `inner`.
+    Companded {
+        law: CompandingLaw,
+        inner: Box<ColorPreset>,
+    },
+    /// Cache the conversions of `inner` in lookup tables, converting every
+    /// opaque color on first decode if `precompute` is set.
+    Cached {
+        #[serde(default)]
+        precompute: bool,
+        inner: Box<ColorPreset>,
+    },
 }
 
 impl Default for ColorPreset {
@@ -45,41 +91,601 @@ impl Default for HueColorPreset {
     }
 }
 
+/// Either a built-in colormap, or a gradient of evenly spaced RGB stops.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ColormapColorPreset {
+    Named { map: ColormapName },
+    Custom { stops: Vec<[u8; 3]> },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ColormapName {
+    Viridis,
+    Magma,
+}
+
+impl From<ColormapName> for Colormap {
+    fn from(name: ColormapName) -> Self {
+        match name {
+            ColormapName::Viridis => Colormap::Viridis,
+            ColormapName::Magma => Colormap::Magma,
+        }
+    }
+}
+
+/// A color with a perceptual lightness, and optionally a hue.
+///
+/// The chroma is fixed, or proportional to the loudness with `from_sample`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct LightnessColorPreset {
+    space: LightnessSpaceName,
+    chroma: f32,
+    from_sample: bool,
+    hue: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LightnessSpaceName {
+    #[default]
+    Oklab,
+    Cielab,
+}
+
+impl From<LightnessSpaceName> for LightnessSpace {
+    fn from(name: LightnessSpaceName) -> Self {
+        match name {
+            LightnessSpaceName::Oklab => LightnessSpace::Oklab,
+            LightnessSpaceName::Cielab => LightnessSpace::Cielab,
+        }
+    }
+}
+
+/// Shades of grey with either 8 or 16 `bits`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct GreyColorPreset {
+    bits: u32,
+}
+
+impl Default for GreyColorPreset {
+    fn default() -> Self {
+        GreyColorPreset { bits: 8 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CompandingLaw {
+    MuLaw,
+    ALaw,
+}
+
+impl From<CompandingLaw> for Companding {
+    fn from(law: CompandingLaw) -> Self {
+        match law {
+            CompandingLaw::MuLaw => Companding::MuLaw,
+            CompandingLaw::ALaw => Companding::ALaw,
+        }
+    }
+}
+
 impl ColorPreset {
-    pub fn to_strategy(&self) -> Box<dyn ColorStrategy + Send> {
+    /// Create a color strategy.
+    ///
+    /// This fails for presets which are invalid.
+    pub fn to_strategy(&self) -> anyhow::Result<Box<dyn ColorStrategy + Send>> {
         use ColorPreset::*;
-        match self {
+        let strategy: Box<dyn ColorStrategy + Send> = match self {
             Hue { options } => Box::new(HueColorStrategy::new(
                 options.saturation,
                 options.value,
             )),
-        }
+            Colormap { options } => Box::new(match options {
+                ColormapColorPreset::Named { map } => {
+                    ColormapColorStrategy::from_colormap((*map).into())
+                }
+                ColormapColorPreset::Custom { stops } => {
+                    if stops.len() < 2 {
+                        bail!(
+                            "a colormap needs at least 2 stops, not {}",
+                            stops.len()
+                        );
+                    }
+                    ColormapColorStrategy::new(stops)
+                }
+            }),
+            Lightness { options } => Box::new(LightnessColorStrategy::new(
+                options.space.into(),
+                if options.from_sample {
+                    Chroma::FromSample(options.chroma)
+                } else {
+                    Chroma::Fixed(options.chroma)
+                },
+                options.hue,
+            )),
+            Grey { options } => {
+                if options.bits != 8 && options.bits != 16 {
+                    bail!("grey must have 8 or 16 bits, not {}", options.bits);
+                }
+                Box::new(GreyColorStrategy::new(options.bits))
+            }
+            Packed { samples } => {
+                if *samples != 2 && *samples != 4 {
+                    bail!("can only pack 2 or 4 samples, not {}", samples);
+                }
+                Box::new(PackedColorStrategy::new(*samples))
+            }
+            Analytic { taps } => {
+                if *taps == 0 {
+                    bail!("the Hilbert transform needs at least one tap");
+                }
+                Box::new(AnalyticColorStrategy::new(*taps))
+            }
+            Companded { law, inner } => Box::new(CompandedColorStrategy::new(
+                inner.to_strategy()?,
+                (*law).into(),
+            )),
+            Cached { precompute, inner } => Box::new(CachedColorStrategy::new(
+                inner.to_strategy()?,
+                if *precompute {
+                    InverseTable::Precomputed
+                } else {
+                    InverseTable::Memoized
+                },
+            )),
+        };
+        Ok(strategy)
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "strategy", content = "options", rename_all = "snake_case")]
 pub enum SpacePreset {
-    Hilbert { size: u32 },
-    Line { length: usize },
+    Hilbert {
+        size: Size,
+    },
+    HilbertVolume {
+        size: Size,
+    },
+    Line {
+        length: usize,
+    },
+    Gosper {
+        order: u32,
+        #[serde(default = "default_cell_size")]
+        cell_size: u32,
+    },
+    Moore {
+        size: Size,
+    },
+    Peano {
+        size: Size,
+    },
+    Raster {
+        width: u32,
+        height: u32,
+    },
+    Rings {
+        size: Size,
+    },
+    Spiral {
+        size: Size,
+    },
+    Tempo {
+        bpm: f32,
+        sample_rate: u32,
+        #[serde(default = "default_channels")]
+        channels: u16,
+        #[serde(default = "default_beats_per_tile")]
+        beats_per_tile: u32,
+        columns: u32,
+        rows: u32,
+    },
+    Permuted {
+        seed: u64,
+        inner: Box<SpacePreset>,
+    },
+    /// Embed an image into a volume one slice deep.
+    Embed {
+        inner: Box<SpacePreset>,
+    },
+    /// Take the image at `position` along `axis` of a volume.
+    Slice {
+        axis: usize,
+        position: u32,
+        inner: Box<SpacePreset>,
+    },
+    /// Permute the axes of an image or a volume.
+    Transpose {
+        axes: Vec<usize>,
+        inner: Box<SpacePreset>,
+    },
+    /// Mirror, transpose, rotate and then offset an image.
+    Transformed {
+        #[serde(default)]
+        mirror_x: bool,
+        #[serde(default)]
+        mirror_y: bool,
+        #[serde(default)]
+        transpose: bool,
+        /// The number of clockwise quarter turns.
+        #[serde(default)]
+        rotate: u32,
+        #[serde(default)]
+        offset: [i32; 2],
+        inner: Box<SpacePreset>,
+    },
+    /// Lay out tiles with the `outer` preset, each filled with the `inner`
+    /// preset.
+    Tiled {
+        outer: Box<SpacePreset>,
+        inner: Box<SpacePreset>,
+    },
+}
+
+/// The side length of a space, or `"auto"` to pick the smallest side which
+/// fits the audio.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(untagged)]
+pub enum Size {
+    Fixed(u32),
+    Auto(AutoSize),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoSize {
+    Auto,
+}
+
+impl Size {
+    fn get(self) -> u32 {
+        match self {
+            Size::Fixed(size) => size,
+            Size::Auto(_) => panic!("size must be fitted to the audio first"),
+        }
+    }
+
+    /// Pick the smallest side accepted by `valid` which holds `samples` in
+    /// the given number of dimensions, if this size is automatic.
+    fn fit(&mut self, samples: usize, dimensions: u32, valid: fn(u32) -> bool) {
+        if let Size::Auto(_) = self {
+            let side = (1..)
+                .find(|&side| {
+                    valid(side) && (side as usize).pow(dimensions) >= samples
+                })
+                .unwrap();
+            *self = Size::Fixed(side);
+        }
+    }
+}
+
+/// Check that `axes` is a permutation of the axes of `N` dimensions.
+fn permutation<const N: usize>(axes: &[usize]) -> anyhow::Result<[usize; N]> {
+    let axes: [usize; N] = match axes.try_into() {
+        Ok(axes) => axes,
+        Err(_) => bail!("expected {} axes to transpose, not {}", N, axes.len()),
+    };
+    let mut sorted = axes;
+    sorted.sort_unstable();
+    if sorted.iter().enumerate().any(|(i, &axis)| i != axis) {
+        bail!("axes to transpose must be a permutation of 0 to {}", N - 1);
+    }
+
+    Ok(axes)
+}
+
+fn is_power_of_three(size: u32) -> bool {
+    (0..=20).any(|order| 3u32.pow(order) == size)
+}
+
+fn default_cell_size() -> u32 {
+    4
+}
+
+fn default_channels() -> u16 {
+    2
+}
+
+fn default_beats_per_tile() -> u32 {
+    4
 }
 
 impl SpacePreset {
-    pub fn to_strategy(&self) -> Box<dyn SpaceStrategy<2> + Send> {
+    /// Whether this is a preset for a volume rather than an image.
+    pub fn is_volume(&self) -> bool {
         use SpacePreset::*;
         match self {
+            HilbertVolume { .. } | Embed { .. } => true,
+            Permuted { inner, .. } | Transpose { inner, .. } => {
+                inner.is_volume()
+            }
+            _ => false,
+        }
+    }
+
+    /// Create a two-dimensional space strategy.
+    ///
+    /// This fails for presets which are invalid, or which are for a volume.
+    pub fn to_strategy(
+        &self,
+    ) -> anyhow::Result<Box<dyn SpaceStrategy<2> + Send>> {
+        use SpacePreset::*;
+        let strategy: Box<dyn SpaceStrategy<2> + Send> = match self {
             Hilbert { size } => {
-                Box::new(HilbertSpaceStrategy::from_size(*size))
+                if !size.get().is_power_of_two() {
+                    bail!(
+                        "hilbert size must be a power of two: {}",
+                        size.get()
+                    );
+                }
+                Box::new(HilbertSpaceStrategy::from_size(size.get()))
+            }
+            HilbertVolume { .. } | Embed { .. } => {
+                bail!("expected a two-dimensional space strategy, not a volume")
             }
             Line { length } => Box::new(SpaceStrategyAdapter::new(
                 LineSpaceStrategy::new(*length),
             )),
+            Gosper { order, cell_size } => {
+                if *cell_size < 2 {
+                    bail!("gosper cell size must be at least 2: {}", cell_size);
+                }
+                Box::new(GosperSpaceStrategy::new(*order, *cell_size))
+            }
+            Moore { size } => {
+                if size.get() < 2 || !size.get().is_power_of_two() {
+                    bail!(
+                        "moore size must be a power of two of at least 2: {}",
+                        size.get()
+                    );
+                }
+                Box::new(MooreSpaceStrategy::from_size(size.get()))
+            }
+            Peano { size } => {
+                if !is_power_of_three(size.get()) {
+                    bail!(
+                        "peano size must be a power of three: {}",
+                        size.get()
+                    );
+                }
+                Box::new(PeanoSpaceStrategy::from_size(size.get()))
+            }
+            Raster { width, height } => {
+                Box::new(RasterSpaceStrategy::new(*width, *height))
+            }
+            Rings { size } => {
+                Box::new(RingSpaceStrategy::from_size(size.get()))
+            }
+            Spiral { size } => {
+                if size.get() == 0 {
+                    bail!("spiral size must not be zero");
+                }
+                Box::new(SpiralSpaceStrategy::from_size(size.get()))
+            }
+            Tempo { .. } => Box::new(
+                self.tempo_strategy()?
+                    .expect("a tempo preset has a tempo strategy"),
+            ),
+            Permuted { seed, inner } => Box::new(PermutedSpaceStrategy::new(
+                inner.to_strategy()?,
+                *seed,
+            )),
+            Slice {
+                axis,
+                position,
+                inner,
+            } => {
+                let inner = inner.to_volume_strategy()?;
+                if *axis >= 3 {
+                    bail!("slice axis must be 0, 1 or 2: {}", axis);
+                }
+                if *position >= inner.length(*axis) {
+                    bail!(
+                        "slice position {} is outside of the volume, which has length {} along axis {}",
+                        position,
+                        inner.length(*axis),
+                        axis
+                    );
+                }
+
+                let mut fixed = [None; 3];
+                fixed[*axis] = Some(*position);
+                Box::new(SliceSpaceStrategy::new(inner, fixed))
+            }
+            Transpose { axes, inner } => Box::new(TransposeSpaceStrategy::new(
+                inner.to_strategy()?,
+                permutation(axes)?,
+            )),
+            Transformed {
+                mirror_x,
+                mirror_y,
+                transpose,
+                rotate,
+                offset,
+                inner,
+            } => Box::new(TransformedSpaceStrategy::new(
+                inner.to_strategy()?,
+                Transform {
+                    mirror_x: *mirror_x,
+                    mirror_y: *mirror_y,
+                    transpose: *transpose,
+                    quarter_turns: *rotate,
+                    offset: *offset,
+                },
+            )),
+            Tiled { outer, inner } => {
+                let inner = inner.to_strategy()?;
+                if inner.size() == 0 {
+                    bail!("tiles must not be empty");
+                }
+                Box::new(TiledSpaceStrategy::new(outer.to_strategy()?, inner))
+            }
+        };
+        Ok(strategy)
+    }
+
+    /// Create a three-dimensional space strategy.
+    ///
+    /// This fails for presets which are invalid, or which are for an image.
+    pub fn to_volume_strategy(
+        &self,
+    ) -> anyhow::Result<Box<dyn SpaceStrategy<3> + Send>> {
+        use SpacePreset::*;
+        Ok(match self {
+            HilbertVolume { size } => {
+                if !size.get().is_power_of_two() {
+                    bail!(
+                        "hilbert volume size must be a power of two: {}",
+                        size.get()
+                    );
+                }
+                Box::new(HilbertVolumeSpaceStrategy::from_size(size.get()))
+            }
+            Permuted { seed, inner } => Box::new(PermutedSpaceStrategy::new(
+                inner.to_volume_strategy()?,
+                *seed,
+            )),
+            Embed { inner } => {
+                Box::new(SpaceStrategyAdapter::new(inner.to_strategy()?))
+            }
+            Transpose { axes, inner } => Box::new(TransposeSpaceStrategy::new(
+                inner.to_volume_strategy()?,
+                permutation(axes)?,
+            )),
+            _ => bail!("expected a volume space strategy, not an image"),
+        })
+    }
+
+    /// Whether any size in this preset is automatic, and so must be fitted to
+    /// the audio with [`SpacePreset::fit`] before use.
+    pub fn is_auto(&self) -> bool {
+        use SpacePreset::*;
+        match self {
+            Hilbert { size }
+            | HilbertVolume { size }
+            | Moore { size }
+            | Peano { size }
+            | Rings { size }
+            | Spiral { size } => matches!(size, Size::Auto(_)),
+            Tiled { outer, inner } => outer.is_auto() || inner.is_auto(),
+            _ => matches!(self.inner(), Some(inner) if inner.is_auto()),
+        }
+    }
+
+    /// Replace any automatic sizes with the smallest sizes which hold
+    /// `samples`.
+    pub fn fit(&mut self, samples: usize) {
+        use SpacePreset::*;
+        let power_of_two = |side: u32| side >= 2 && side.is_power_of_two();
+        match self {
+            Hilbert { size } | Moore { size } => {
+                size.fit(samples, 2, power_of_two)
+            }
+            HilbertVolume { size } => size.fit(samples, 3, power_of_two),
+            Peano { size } => size.fit(samples, 2, is_power_of_three),
+            Rings { size } | Spiral { size } => size.fit(samples, 2, |_| true),
+            Tiled { outer, inner } => {
+                // a tile only needs to hold its share of the samples, which
+                // is about the square root of them if the grid of tiles is
+                // automatic too
+                let tile_samples = if !outer.is_auto() {
+                    match outer.to_strategy() {
+                        Ok(grid) => samples.div_ceil(grid.size().max(1)),
+                        Err(_) => samples,
+                    }
+                } else if inner.is_auto() {
+                    (samples as f64).sqrt().ceil() as usize
+                } else {
+                    samples
+                };
+                inner.fit(tile_samples);
+                if let Ok(tile) = inner.to_strategy() {
+                    outer.fit(samples.div_ceil(tile.size()));
+                }
+            }
+            Permuted { inner, .. }
+            | Embed { inner }
+            | Slice { inner, .. }
+            | Transpose { inner, .. }
+            | Transformed { inner, .. } => inner.fit(samples),
+            _ => {}
         }
     }
+
+    /// The preset wrapped by this one, if any.
+    fn inner(&self) -> Option<&SpacePreset> {
+        match self {
+            SpacePreset::Permuted { inner, .. }
+            | SpacePreset::Embed { inner }
+            | SpacePreset::Slice { inner, .. }
+            | SpacePreset::Transpose { inner, .. }
+            | SpacePreset::Transformed { inner, .. }
+            | SpacePreset::Tiled { inner, .. } => Some(inner),
+            _ => None,
+        }
+    }
+
+    /// The sample rate of the audio before it was resampled for encoding, if
+    /// this strategy requires resampling.
+    pub fn original_sample_rate(&self) -> Option<u32> {
+        match self {
+            SpacePreset::Tempo { sample_rate, .. } => Some(*sample_rate),
+            _ => self.inner()?.original_sample_rate(),
+        }
+    }
+
+    /// The exact sample rate at which audio is laid out by this strategy, if
+    /// it requires resampling.
+    pub fn encoded_sample_rate(&self) -> anyhow::Result<Option<f64>> {
+        match self.tempo_strategy()? {
+            Some(strategy) => Ok(Some(strategy.sample_rate())),
+            None => match self.inner() {
+                Some(inner) => inner.encoded_sample_rate(),
+                None => Ok(None),
+            },
+        }
+    }
+
+    /// The number of channels of the audio which this strategy lays out, if
+    /// it depends on it.
+    pub fn channels(&self) -> Option<u16> {
+        match self {
+            SpacePreset::Tempo { channels, .. } => Some(*channels),
+            _ => self.inner()?.channels(),
+        }
+    }
+
+    fn tempo_strategy(&self) -> anyhow::Result<Option<TempoSpaceStrategy>> {
+        Ok(match self {
+            SpacePreset::Tempo {
+                bpm,
+                sample_rate,
+                channels,
+                beats_per_tile,
+                columns,
+                rows,
+            } => Some(TempoSpaceStrategy::new(
+                *bpm,
+                *sample_rate,
+                *channels,
+                *beats_per_tile,
+                *columns,
+                *rows,
+            )?),
+            _ => None,
+        })
+    }
 }
 
 impl Default for SpacePreset {
     fn default() -> SpacePreset {
-        SpacePreset::Hilbert { size: 512 }
+        SpacePreset::Hilbert {
+            size: Size::Fixed(512),
+        }
     }
 }
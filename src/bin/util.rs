@@ -7,3 +7,18 @@ pub fn validate_is_file(arg: &str) -> Result<(), String> {
         Ok(())
     }
 }
+
+pub fn validate_exists(arg: &str) -> Result<(), String> {
+    if !PathBuf::from(arg).exists() {
+        Err(String::from("does not exist"))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn validate_sample_bits(arg: &str) -> Result<(), String> {
+    match arg.parse::<u32>() {
+        Ok(1..=16) => Ok(()),
+        _ => Err(String::from("must be between 1 and 16 bits")),
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+
+use hilly_sounds::{strategy::color::PixelFormat, Volume};
+
+use crate::metadata::{load_png, save_png};
+
+/// Whether `path` refers to a (multi-page) TIFF file.
+pub fn is_tiff(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("tif" | "tiff")
+    )
+}
+
+/// Save a volume in `format`, either as a multi-page TIFF file if `path` has a
+/// TIFF extension, or as a directory of numbered PNG slices otherwise.
+///
+/// The number of samples the volume holds is recorded in every slice, if given.
+pub fn save_volume(
+    volume: &Volume,
+    path: &Path,
+    length: Option<usize>,
+    format: PixelFormat,
+) -> anyhow::Result<()> {
+    if is_tiff(path) {
+        check_tiff_format(format)?;
+        return save_tiff(volume, path, length);
+    }
+
+    fs::create_dir_all(path)?;
+    for (z, slice) in volume.iter().enumerate() {
+        let slice_path = path.join(format!("{:04}.png", z));
+        save_png(slice, &slice_path, length, format)?;
+    }
+
+    Ok(())
+}
+
+/// The paths of the PNG slices in a volume directory, ordered by file name.
+pub fn slice_paths(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut slice_paths = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    slice_paths.retain(|path| {
+        path.extension().and_then(|ext| ext.to_str()) == Some("png")
+    });
+    slice_paths.sort();
+
+    Ok(slice_paths)
+}
+
+/// Load a volume in `format` saved by [`save_volume`].
+///
+/// The slices in a directory are ordered by file name, so they may be
+/// reordered by renaming them.
+pub fn load_volume(path: &Path, format: PixelFormat) -> anyhow::Result<Volume> {
+    if is_tiff(path) {
+        check_tiff_format(format)?;
+        return load_tiff(path);
+    }
+
+    if !path.is_dir() {
+        bail!("volume must be a directory of slices or a TIFF file");
+    }
+
+    let volume = slice_paths(path)?
+        .iter()
+        .map(|path| load_png(path, format))
+        .collect::<anyhow::Result<Volume>>()?;
+
+    if let Some(first) = volume.first() {
+        if volume.iter().any(|s| s.dimensions() != first.dimensions()) {
+            bail!("all slices of a volume must have the same dimensions");
+        }
+    }
+
+    Ok(volume)
+}
+
+/// Check that volumes in `format` can be stored as TIFF files, which only
+/// hold 8-bit RGBA pages.
+fn check_tiff_format(format: PixelFormat) -> anyhow::Result<()> {
+    if format != PixelFormat::Rgba8 {
+        bail!("volumes of 16-bit greys must be saved as PNG slices");
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "tiff")]
+fn save_tiff(
+    volume: &Volume,
+    path: &Path,
+    length: Option<usize>,
+) -> anyhow::Result<()> {
+    use tiff::{
+        encoder::{colortype, TiffEncoder},
+        tags::Tag,
+    };
+
+    use crate::metadata::LENGTH_KEY;
+
+    let mut encoder = TiffEncoder::new(fs::File::create(path)?)?;
+    for slice in volume {
+        let mut image = encoder
+            .new_image::<colortype::RGBA8>(slice.width(), slice.height())?;
+        if let Some(length) = length {
+            let description = format!("{}={}", LENGTH_KEY, length);
+            image
+                .encoder()
+                .write_tag(Tag::ImageDescription, description.as_str())?;
+        }
+        image.write_data(slice.as_raw())?;
+    }
+
+    Ok(())
+}
+
+/// Read the number of samples recorded in a TIFF file by [`save_tiff`].
+#[cfg(feature = "tiff")]
+pub fn read_tiff_length(path: &Path) -> anyhow::Result<Option<usize>> {
+    use tiff::{decoder::Decoder, tags::Tag};
+
+    use crate::metadata::LENGTH_KEY;
+
+    let mut decoder = Decoder::new(fs::File::open(path)?)?;
+    let description = match decoder.find_tag(Tag::ImageDescription)? {
+        Some(value) => value.into_string()?,
+        None => return Ok(None),
+    };
+
+    Ok(description
+        .strip_prefix(LENGTH_KEY)
+        .and_then(|rest| rest.strip_prefix('='))
+        .and_then(|value| value.parse().ok()))
+}
+
+#[cfg(feature = "tiff")]
+fn load_tiff(path: &Path) -> anyhow::Result<Volume> {
+    use anyhow::Context;
+    use tiff::{
+        decoder::{Decoder, DecodingResult},
+        ColorType,
+    };
+
+    let mut decoder = Decoder::new(fs::File::open(path)?)?;
+    let mut volume = Volume::new();
+
+    loop {
+        if decoder.colortype()? != ColorType::RGBA(8) {
+            bail!("TIFF pages must be 8-bit RGBA");
+        }
+
+        let (width, height) = decoder.dimensions()?;
+        let slice = match decoder.read_image()? {
+            DecodingResult::U8(buffer) => {
+                nannou::image::RgbaImage::from_raw(width, height, buffer)
+                    .context("TIFF page has the wrong size")?
+            }
+            _ => bail!("TIFF pages must be 8-bit RGBA"),
+        };
+        volume.push(slice);
+
+        if !decoder.more_images() {
+            break;
+        }
+        decoder.next_image()?;
+    }
+
+    Ok(volume)
+}
+
+#[cfg(not(feature = "tiff"))]
+fn save_tiff(
+    _volume: &Volume,
+    _path: &Path,
+    _length: Option<usize>,
+) -> anyhow::Result<()> {
+    bail!("TIFF support requires the tiff feature")
+}
+
+#[cfg(not(feature = "tiff"))]
+pub fn read_tiff_length(_path: &Path) -> anyhow::Result<Option<usize>> {
+    bail!("TIFF support requires the tiff feature")
+}
+
+#[cfg(not(feature = "tiff"))]
+fn load_tiff(_path: &Path) -> anyhow::Result<Volume> {
+    bail!("TIFF support requires the tiff feature")
+}
@@ -1,34 +1,203 @@
-#![feature(int_log)]
+#![feature(int_log, int_roundings)]
 
-use std::io;
+use std::{collections::VecDeque, error, fmt, io, ops::Range};
 
+use dsp::num_complex::Complex32;
 use hound::WavWriter;
 use nannou::image::{self, RgbaImage};
-use strategy::{ColorStrategy, SpaceStrategy};
+use strategy::{ColorStrategy, ComplexColorStrategy, SpaceStrategy};
 
+pub mod arrange;
+pub mod resample;
+pub mod spectrum;
+pub mod stego;
 pub mod strategy;
+pub mod video;
 
 /// An *n*-dimensional coordinate.
 pub type Coord<const N: usize> = [u32; N];
 
-/// A pixel in an image, represented by a color and a two-dimensional
+/// A pixel in an image, represented by a color and an *n*-dimensional
 /// coordinate.
-pub type PixelData = (Coord<2>, image::Rgba<u8>);
+pub type PixelData<const N: usize = 2> = (Coord<N>, image::Rgba<u8>);
+
+/// A three-dimensional volume of pixels, stored as a stack of images along the
+/// third axis.
+pub type Volume = Vec<RgbaImage>;
+
+/// Storage for an *n*-dimensional grid of pixels.
+pub trait Canvas<const N: usize> {
+    /// Create a canvas with the given lengths, filled with transparent pixels.
+    fn blank(lengths: [u32; N]) -> Self;
+
+    /// The length of each dimension.
+    fn lengths(&self) -> [u32; N];
+
+    /// Get the pixel at `coord`.
+    ///
+    /// This may panic if `coord` is out of bounds.
+    fn pixel(&self, coord: Coord<N>) -> &image::Rgba<u8>;
+
+    /// Set the pixel at `coord`.
+    ///
+    /// This may panic if `coord` is out of bounds.
+    fn set_pixel(&mut self, coord: Coord<N>, color: image::Rgba<u8>);
+}
+
+impl Canvas<2> for RgbaImage {
+    fn blank([width, height]: [u32; 2]) -> Self {
+        RgbaImage::new(width, height)
+    }
+
+    fn lengths(&self) -> [u32; 2] {
+        [self.width(), self.height()]
+    }
+
+    fn pixel(&self, [x, y]: Coord<2>) -> &image::Rgba<u8> {
+        self.get_pixel(x, y)
+    }
+
+    fn set_pixel(&mut self, [x, y]: Coord<2>, color: image::Rgba<u8>) {
+        self.put_pixel(x, y, color)
+    }
+}
+
+impl Canvas<3> for Volume {
+    fn blank([width, height, depth]: [u32; 3]) -> Self {
+        (0..depth).map(|_| RgbaImage::new(width, height)).collect()
+    }
+
+    fn lengths(&self) -> [u32; 3] {
+        let (width, height) =
+            self.first().map_or((0, 0), |slice| slice.dimensions());
+        [width, height, self.len() as u32]
+    }
+
+    fn pixel(&self, [x, y, z]: Coord<3>) -> &image::Rgba<u8> {
+        self[z as usize].get_pixel(x, y)
+    }
+
+    fn set_pixel(&mut self, [x, y, z]: Coord<3>, color: image::Rgba<u8>) {
+        self[z as usize].put_pixel(x, y, color)
+    }
+}
 
 /// Encoding algorithm that combines color and space strategies with a stream of
 /// samples to produce an image.
-pub struct Encoder<S, I>
+pub struct Encoder<S, I, const N: usize = 2>
 where
     S: hound::Sample + SampleConvert,
     I: Iterator<Item = S>,
 {
     index: usize,
+    position: usize,
+    channels: usize,
+    samples: Vec<f32>,
+    quantized: Vec<i16>,
     iter: I,
+    ended: bool,
+    fill: FillPolicy,
+    history: Vec<f32>,
+    window: VecDeque<f32>,
+    past: usize,
+    channel_window: Vec<f32>,
+    alpha: AlphaSource,
+    level: f32,
+    dither: Dither,
+    rng: u64,
+    errors: Vec<f32>,
     color_strategy: Box<dyn ColorStrategy + Send>,
-    space_strategy: Box<dyn SpaceStrategy<2> + Send>,
+    space_strategy: Box<dyn SpaceStrategy<N> + Send>,
+}
+
+/// How an [`Encoder`] fills the rest of the space after the audio ends.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FillPolicy {
+    /// Leave the rest of the pixels transparent.
+    #[default]
+    Transparent,
+    /// Fill the rest with the color of silence.
+    Silence,
+    /// Repeat the audio from the start.
+    Loop,
+    /// Play the audio backwards, then forwards again, and so on.
+    Mirror,
+}
+
+/// How a [`Decoder`] treats fully transparent pixels, such as those left
+/// behind by an [`Encoder`] after the audio ends.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TransparentPixels {
+    /// Decode transparent pixels as silence.
+    #[default]
+    Silence,
+    /// Stop decoding at the first transparent pixel.
+    EndOfStream,
+    /// Leave transparent pixels out of the decoded audio.
+    Skip,
+}
+
+/// What an [`Encoder`] writes to the alpha channel.
+///
+/// Alpha values written from audio are never fully transparent, so that they
+/// are not mistaken for padding.
+#[derive(Default)]
+pub enum AlphaSource {
+    /// Leave every pixel fully opaque.
+    #[default]
+    Opaque,
+    /// The amplitude envelope of the encoded audio, which follows its peaks
+    /// and then decays by a factor of *e* every `release` samples.
+    Envelope { release: f32 },
+    /// A second signal, quantized to 8 bits, with silence after it ends.
+    Signal(Box<dyn Iterator<Item = i16> + Send>),
 }
 
-impl<S, I> Encoder<S, I>
+/// How a [`Decoder`] uses the alpha channel of pixels which are not fully
+/// transparent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Ignore the alpha channel.
+    #[default]
+    Ignore,
+    /// Multiply each sample by its alpha, as a gain between 0 and 1.
+    Gain,
+    /// Decode the second signal written by [`AlphaSource::Signal`] instead of
+    /// the color.
+    Signal,
+}
+
+/// How an [`Encoder`] dithers samples before the color strategy quantizes
+/// them.
+///
+/// Both happen in the order of the curve, so that the error is spread out over
+/// time rather than over the image.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dither {
+    /// The quantization step of the color strategy in samples, which scales
+    /// the triangular (TPDF) dither added to each sample, or zero to not add
+    /// dither.
+    pub step: f32,
+    /// Whether to subtract the quantization error of each sample from the
+    /// next one in the same channel, which moves the error towards high
+    /// frequencies.
+    ///
+    /// Only color strategies which quantize every sample on its own, with one
+    /// sample per pixel, support noise shaping.
+    pub noise_shaping: bool,
+}
+
+/// Quantize a sample to a non-transparent alpha value.
+fn sample_to_alpha(sample: i16) -> u8 {
+    ((sample as i32 + 0x8000) >> 8).max(1) as u8
+}
+
+/// The inverse of [`sample_to_alpha`].
+fn alpha_to_sample(alpha: u8) -> i16 {
+    ((alpha as i32) << 8) as i16 ^ i16::MIN
+}
+
+impl<S, I, const N: usize> Encoder<S, I, N>
 where
     S: hound::Sample + SampleConvert,
     I: Iterator<Item = S>,
@@ -36,48 +205,299 @@ where
     pub fn new(
         iter: I,
         color_strategy: Box<dyn ColorStrategy + Send>,
-        space_strategy: Box<dyn SpaceStrategy<2> + Send>,
+        space_strategy: Box<dyn SpaceStrategy<N> + Send>,
     ) -> Self {
+        assert!(
+            color_strategy.context() == 0
+                || color_strategy.samples_per_pixel() == 1,
+            "color strategies with context must take one sample per pixel"
+        );
+
         Encoder {
             index: 0,
+            position: 0,
+            channels: 1,
+            samples: vec![0.; color_strategy.samples_per_pixel()],
+            quantized: vec![0; color_strategy.samples_per_pixel()],
             iter,
+            ended: false,
+            fill: FillPolicy::default(),
+            history: Vec::new(),
+            window: VecDeque::new(),
+            past: 0,
+            channel_window: Vec::new(),
+            alpha: AlphaSource::default(),
+            level: 0.,
+            dither: Dither::default(),
+            rng: 0,
+            errors: Vec::new(),
             color_strategy,
             space_strategy,
         }
     }
+
+    /// Treat the samples as `channels` interleaved channels, rather than one.
+    pub fn with_channels(mut self, channels: u16) -> Self {
+        assert!(channels > 0);
+        self.channels = channels as usize;
+        self
+    }
+
+    /// Write the alpha channel from `alpha`.
+    pub fn with_alpha(mut self, alpha: AlphaSource) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// The alpha value to write along with `sample`, if not opaque.
+    fn next_alpha(&mut self, sample: i16) -> Option<u8> {
+        match &mut self.alpha {
+            AlphaSource::Opaque => None,
+            AlphaSource::Envelope { release } => {
+                let decay = (-1. / *release).exp();
+                self.level =
+                    (sample.unsigned_abs() as f32).max(self.level * decay);
+                Some(((self.level / 32768. * 255.).round() as u8).max(1))
+            }
+            AlphaSource::Signal(iter) => {
+                Some(sample_to_alpha(iter.next().unwrap_or(0)))
+            }
+        }
+    }
+
+    /// Dither samples according to `dither`.
+    ///
+    /// This panics if noise shaping is requested for a color strategy which
+    /// packs several samples into each pixel or looks at the samples around
+    /// each one.
+    pub fn with_dither(mut self, dither: Dither) -> Self {
+        assert!(
+            !dither.noise_shaping
+                || (self.color_strategy.samples_per_pixel() == 1
+                    && self.color_strategy.context() == 0),
+            "noise shaping requires one sample per pixel without context"
+        );
+        self.dither = dither;
+        self
+    }
+
+    /// Apply the dither and noise shaping to the sample which was just read.
+    fn dither_sample(&mut self, sample: f32) -> f32 {
+        if self.dither == Dither::default() {
+            return sample;
+        }
+
+        // each channel carries its own error, so that they do not leak into
+        // each other
+        self.errors.resize(self.channels, 0.);
+        let channel = (self.position - 1) % self.channels;
+
+        let mut target = sample;
+        if self.dither.noise_shaping {
+            target -= self.errors[channel];
+        }
+
+        let mut uniform =
+            || (splitmix64(&mut self.rng) >> 40) as f32 / 2f32.powi(24);
+        let noise = (uniform() + uniform() - 1.) * self.dither.step / 32768.;
+        let dithered = (target + noise).clamp(-1., 1.);
+
+        if self.dither.noise_shaping {
+            let quantized = self.color_strategy.color_to_sample_f32(
+                &self.color_strategy.sample_to_color_f32(dithered),
+            );
+            self.errors[channel] = quantized - target;
+        }
+
+        dithered
+    }
+
+    /// Fill the rest of the space according to `fill` after the audio ends.
+    pub fn with_fill(mut self, fill: FillPolicy) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    /// The sample to fill with at the current position, after the audio ended.
+    ///
+    /// The audio is repeated in whole frames, so that the channels stay in
+    /// place. Any incomplete frame at its end is completed with silence and
+    /// left out of the repeats.
+    fn fill_sample(&self) -> Option<f32> {
+        let channels = self.channels;
+        let frames = self.history.len() / channels;
+        let start = self.history.len().div_ceil(channels) * channels;
+        let offset = match self.position.checked_sub(start) {
+            Some(offset) => offset,
+            None if self.fill == FillPolicy::Transparent => return None,
+            None => return Some(0.),
+        };
+        let (frame, channel) = (offset / channels, offset % channels);
+        let frame = match self.fill {
+            FillPolicy::Transparent => return None,
+            FillPolicy::Silence => return Some(0.),
+            _ if frames == 0 => return None,
+            FillPolicy::Loop => frame % frames,
+            FillPolicy::Mirror => {
+                let frame = frame % (2 * frames);
+                if frame < frames {
+                    frames - 1 - frame
+                } else {
+                    frame - frames
+                }
+            }
+        };
+
+        Some(self.history[frame * channels + channel])
+    }
+
+    /// The next sample of audio, or of the fill after it ends.
+    fn next_sample(&mut self) -> Option<f32> {
+        let next = if self.ended { None } else { self.iter.next() };
+        let sample = match next {
+            Some(sample) => {
+                // floating point audio can go beyond full scale
+                let sample = sample.convert_to_f32().clamp(-1., 1.);
+                if matches!(self.fill, FillPolicy::Loop | FillPolicy::Mirror) {
+                    self.history.push(sample);
+                }
+                sample
+            }
+            None => {
+                self.ended = true;
+                self.fill_sample()?
+            }
+        };
+        self.position += 1;
+
+        Some(sample)
+    }
+
+    /// The color of the next pixel and the alpha to write along with it.
+    fn next_color(&mut self) -> Option<(image::Rgba<u8>, Option<u8>)> {
+        // a pixel is only left out if none of its samples remain, otherwise
+        // the missing samples are silent
+        let mut alpha = None;
+        for i in 0..self.samples.len() {
+            let sample = match self.next_sample() {
+                Some(sample) => sample,
+                None if i == 0 => return None,
+                None => 0.,
+            };
+            self.samples[i] = self.dither_sample(sample);
+            alpha = self.next_alpha(sample.convert_to_i16());
+        }
+
+        let color = if let [sample] = self.samples[..] {
+            self.color_strategy.sample_to_color_f32(sample)
+        } else {
+            for (quantized, sample) in
+                self.quantized.iter_mut().zip(&self.samples)
+            {
+                *quantized = sample.convert_to_i16();
+            }
+            self.color_strategy.samples_to_color(&self.quantized)
+        };
+
+        Some((color, alpha))
+    }
+
+    /// Like [`Encoder::next_color`], but for color strategies which look at
+    /// the samples around each one.
+    ///
+    /// The interleaved samples of every channel are buffered, and the color
+    /// strategy sees up to `context` samples of the same channel on either
+    /// side of the current one, fewer at the start and end of the audio.
+    fn next_color_in_context(
+        &mut self,
+    ) -> Option<(image::Rgba<u8>, Option<u8>)> {
+        let context = self.color_strategy.context() * self.channels;
+        while self.window.len() <= self.past + context {
+            match self.next_sample() {
+                Some(sample) => {
+                    let sample = self.dither_sample(sample);
+                    self.window.push_back(sample);
+                }
+                None => break,
+            }
+        }
+
+        let center = self.past;
+        let sample = *self.window.get(center)?;
+        let alpha = self.next_alpha(sample.convert_to_i16());
+
+        // the samples of the same channel are a whole number of frames away
+        self.channel_window.clear();
+        self.channel_window.extend(
+            self.window
+                .iter()
+                .skip(center % self.channels)
+                .step_by(self.channels),
+        );
+        let color = self.color_strategy.sample_to_color_in_context(
+            &self.channel_window,
+            center / self.channels,
+        );
+
+        if self.past < context {
+            self.past += 1;
+        } else {
+            self.window.pop_front();
+        }
+
+        Some((color, alpha))
+    }
 }
 
-impl<S, I> Iterator for Encoder<S, I>
+impl<S, I, const N: usize> Iterator for Encoder<S, I, N>
 where
     S: hound::Sample + SampleConvert,
     I: Iterator<Item = S>,
 {
-    type Item = PixelData;
+    type Item = PixelData<N>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.index >= self.space_strategy.size() {
             return None;
         }
 
-        if let Some(sample) = self.iter.next() {
-            let coords = self
-                .space_strategy
-                .index_to_coord(self.index)
-                .expect("could not get coordinate from index");
-            self.index += 1;
+        let (mut color, alpha) = if self.color_strategy.context() > 0 {
+            self.next_color_in_context()?
+        } else {
+            self.next_color()?
+        };
 
-            let color =
-                self.color_strategy.sample_to_color(sample.convert_to_i16());
+        let coords = self
+            .space_strategy
+            .index_to_coord(self.index)
+            .expect("could not get coordinate from index");
+        self.index += 1;
 
-            Some((coords, color))
-        } else {
-            None
+        if let Some(alpha) = alpha {
+            if !self.color_strategy.uses_alpha() {
+                color.0[3] = alpha;
+            }
         }
+
+        Some((coords, color))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
         // TODO also take into account size from options
-        self.iter.size_hint()
+        match self.fill {
+            FillPolicy::Transparent => {
+                let samples_per_pixel = self.samples.len();
+                let (lower, upper) = self.iter.size_hint();
+                (
+                    lower.div_ceil(samples_per_pixel),
+                    upper.map(|upper| upper.div_ceil(samples_per_pixel)),
+                )
+            }
+            _ => {
+                let remaining = self.space_strategy.size() - self.index;
+                (remaining, Some(remaining))
+            }
+        }
     }
 }
 
@@ -91,82 +511,313 @@ where
     S: hound::Sample + SampleConvert,
     I: Iterator<Item = S>,
 {
-    let mut image = image::ImageBuffer::new(
-        space_strategy.length(0),
-        space_strategy.length(1),
-    );
+    encode_canvas(
+        iter,
+        1,
+        FillPolicy::default(),
+        AlphaSource::default(),
+        Dither::default(),
+        color_strategy,
+        space_strategy,
+    )
+}
+
+/// Encode a canvas of any dimension from a stream of samples with `channels`
+/// interleaved channels, filling the rest of the space according to `fill` if
+/// the samples run out, writing the alpha channel from `alpha` and dithering
+/// according to `dither`.
+pub fn encode_canvas<S, I, C, const N: usize>(
+    iter: I,
+    channels: u16,
+    fill: FillPolicy,
+    alpha: AlphaSource,
+    dither: Dither,
+    color_strategy: Box<dyn ColorStrategy + Send>,
+    space_strategy: Box<dyn SpaceStrategy<N> + Send>,
+) -> C
+where
+    S: hound::Sample + SampleConvert,
+    I: Iterator<Item = S>,
+    C: Canvas<N>,
+{
+    let mut lengths = [0; N];
+    for (dimension, length) in lengths.iter_mut().enumerate() {
+        *length = space_strategy.length(dimension);
+    }
+    let mut canvas = C::blank(lengths);
+
+    let mut encoder = Encoder::new(iter, color_strategy, space_strategy)
+        .with_channels(channels)
+        .with_fill(fill)
+        .with_alpha(alpha)
+        .with_dither(dither);
+
+    while let Some((_, color)) = encoder.next() {
+        // fill every pixel covered by the point which was just encoded
+        encoder
+            .space_strategy
+            .for_each_pixel(encoder.index - 1, &mut |coord| {
+                canvas.set_pixel(coord, color)
+            });
+    }
+
+    canvas
+}
+
+/// Encode a canvas of any dimension from a stream of complex values, one per
+/// point of the space strategy, leaving the rest of the space transparent.
+pub fn encode_complex_canvas<I, C, const N: usize>(
+    iter: I,
+    color_strategy: Box<dyn ComplexColorStrategy + Send>,
+    space_strategy: Box<dyn SpaceStrategy<N> + Send>,
+) -> C
+where
+    I: Iterator<Item = Complex32>,
+    C: Canvas<N>,
+{
+    let mut lengths = [0; N];
+    for (dimension, length) in lengths.iter_mut().enumerate() {
+        *length = space_strategy.length(dimension);
+    }
+    let mut canvas = C::blank(lengths);
+
+    for (index, value) in iter.take(space_strategy.size()).enumerate() {
+        let color = color_strategy.value_to_color(value);
+        space_strategy
+            .for_each_pixel(index, &mut |coord| canvas.set_pixel(coord, color));
+    }
+
+    canvas
+}
 
-    let encoder = Encoder::new(iter, color_strategy, space_strategy);
+/// An error returned when a canvas does not have the size that the space
+/// strategy it is decoded with expects.
+#[derive(Debug)]
+pub struct SizeMismatch {
+    pub dimension: usize,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl fmt::Display for SizeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "canvas has length {} in dimension {}, but the space strategy \
+             expects {}",
+            self.actual, self.dimension, self.expected
+        )
+    }
+}
 
-    for ([x, y], color) in encoder {
-        image.put_pixel(x, y, color);
+impl error::Error for SizeMismatch {}
+
+/// Decode the complex values of every point of the space strategy from a
+/// canvas, in order.
+pub fn decode_complex_canvas<C, const N: usize>(
+    canvas: &C,
+    color_strategy: Box<dyn ComplexColorStrategy + Send>,
+    space_strategy: Box<dyn SpaceStrategy<N> + Send>,
+) -> Result<Vec<Complex32>, SizeMismatch>
+where
+    C: Canvas<N>,
+{
+    for (dimension, actual) in canvas.lengths().into_iter().enumerate() {
+        let expected = space_strategy.length(dimension);
+        if actual != expected {
+            return Err(SizeMismatch {
+                dimension,
+                expected,
+                actual,
+            });
+        }
     }
 
-    image
+    Ok((0..space_strategy.size())
+        .map(|index| {
+            let coord = space_strategy
+                .index_to_coord(index)
+                .expect("could not get coordinate from index");
+            color_strategy.color_to_value(canvas.pixel(coord))
+        })
+        .collect())
 }
 
 /// Decoding algorithm that combines color and space strategies with an image to
 /// produce a stream of samples.
-pub struct Decoder {
+pub struct Decoder<C = RgbaImage, const N: usize = 2>
+where
+    C: Canvas<N>,
+{
     index: usize,
-    image: RgbaImage,
+    length: usize,
+    samples: Vec<f32>,
+    quantized: Vec<i16>,
+    buffered: Range<usize>,
+    channels: usize,
+    states: Vec<f32>,
+    transparent: TransparentPixels,
+    alpha: AlphaMode,
+    image: C,
     color_strategy: Box<dyn ColorStrategy + Send>,
-    space_strategy: Box<dyn SpaceStrategy<2> + Send>,
+    space_strategy: Box<dyn SpaceStrategy<N> + Send>,
 }
 
-impl Decoder {
+impl<C, const N: usize> Decoder<C, N>
+where
+    C: Canvas<N>,
+{
     pub fn new(
-        image: RgbaImage,
+        image: C,
         color_strategy: Box<dyn ColorStrategy + Send>,
-        space_strategy: Box<dyn SpaceStrategy<2> + Send>,
+        space_strategy: Box<dyn SpaceStrategy<N> + Send>,
     ) -> Self {
-        assert!(image.width() == space_strategy.length(0));
-        assert!(image.height() == space_strategy.length(0));
+        for (dimension, length) in image.lengths().into_iter().enumerate() {
+            assert!(length == space_strategy.length(dimension));
+        }
 
+        let samples_per_pixel = color_strategy.samples_per_pixel();
         Decoder {
             index: 0,
+            length: space_strategy.size() * samples_per_pixel,
+            samples: vec![0.; samples_per_pixel],
+            quantized: vec![0; samples_per_pixel],
+            buffered: 0..0,
+            channels: 1,
+            states: Vec::new(),
+            transparent: TransparentPixels::default(),
+            alpha: AlphaMode::default(),
             image,
             color_strategy,
             space_strategy,
         }
     }
-}
 
-impl Iterator for Decoder {
-    type Item = i16;
+    /// Stop decoding after `length` samples, such as to trim the padding after
+    /// audio which did not fill the entire space.
+    pub fn with_length(mut self, length: usize) -> Self {
+        self.length =
+            length.min(self.space_strategy.size() * self.samples.len());
+        self
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.index >= self.space_strategy.size() {
-            return None;
-        }
+    /// Treat the samples as `channels` interleaved channels, rather than one,
+    /// for color strategies which carry state from one sample of a channel to
+    /// the next.
+    pub fn with_channels(mut self, channels: u16) -> Self {
+        assert!(channels > 0);
+        self.channels = channels as usize;
+        self
+    }
 
-        let [x, y] = self
-            .space_strategy
-            .index_to_coord(self.index)
-            .expect("could not get coordinate from index");
-        self.index += 1;
+    /// Treat fully transparent pixels according to `transparent`.
+    pub fn with_transparent(mut self, transparent: TransparentPixels) -> Self {
+        self.transparent = transparent;
+        self
+    }
 
-        let color = self.image.get_pixel(x, y);
+    /// Use the alpha channel according to `alpha`.
+    pub fn with_alpha(mut self, alpha: AlphaMode) -> Self {
+        self.alpha = alpha;
+        self
+    }
 
-        let sample = self.color_strategy.color_to_sample(color);
+    /// Decode the next sample between -1 and 1, with the full precision of
+    /// the color strategy.
+    pub fn next_f32(&mut self) -> Option<f32> {
+        let samples_per_pixel = self.samples.len();
+        let uses_alpha = self.color_strategy.uses_alpha();
 
-        Some(sample)
+        loop {
+            if let Some(i) = self.buffered.next() {
+                return Some(self.samples[i]);
+            }
+
+            let position = self.index * samples_per_pixel;
+            if position >= self.length {
+                break;
+            }
+
+            let coord = self
+                .space_strategy
+                .index_to_coord(self.index)
+                .expect("could not get coordinate from index");
+            self.index += 1;
+
+            let color = *self.image.pixel(coord);
+            let alpha = color.0[3];
+            if alpha == 0 && !uses_alpha {
+                match self.transparent {
+                    TransparentPixels::Silence => self.samples.fill(0.),
+                    TransparentPixels::EndOfStream => break,
+                    TransparentPixels::Skip => continue,
+                }
+            } else if self.alpha == AlphaMode::Signal && !uses_alpha {
+                self.samples.fill(alpha_to_sample(alpha).convert_to_f32());
+            } else {
+                if let [sample] = &mut self.samples[..] {
+                    *sample = if self.color_strategy.context() > 0 {
+                        self.states.resize(self.channels, 0.);
+                        let channel = position % self.channels;
+                        self.color_strategy.color_to_sample_in_context(
+                            &color,
+                            &mut self.states[channel],
+                        )
+                    } else {
+                        self.color_strategy.color_to_sample_f32(&color)
+                    };
+                } else {
+                    self.color_strategy
+                        .color_to_samples(&color, &mut self.quantized);
+                    for (sample, quantized) in
+                        self.samples.iter_mut().zip(&self.quantized)
+                    {
+                        *sample = quantized.convert_to_f32();
+                    }
+                }
+                if self.alpha == AlphaMode::Gain && !uses_alpha {
+                    for sample in &mut self.samples {
+                        *sample *= alpha as f32 / 255.;
+                    }
+                }
+            }
+
+            // the last pixel may hold samples past the end
+            self.buffered = 0..samples_per_pixel.min(self.length - position);
+        }
+
+        self.index = self.length.div_ceil(samples_per_pixel);
+        None
+    }
+}
+
+impl<C, const N: usize> Iterator for Decoder<C, N>
+where
+    C: Canvas<N>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_f32().map(SampleConvert::convert_to_i16)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (0, Some(self.space_strategy.size()))
+        let position = self.index * self.samples.len();
+        let remaining = self.length.saturating_sub(position);
+        (0, Some(remaining + self.buffered.len()))
     }
 }
 
 /// Decode a stream of samples from an image.
-pub fn decode_image<W>(
-    image: RgbaImage,
+pub fn decode_image<W, C, const N: usize>(
+    image: C,
     writer: &mut WavWriter<W>,
     color_strategy: Box<dyn ColorStrategy + Send>,
-    space_strategy: Box<dyn SpaceStrategy<2> + Send>,
+    space_strategy: Box<dyn SpaceStrategy<N> + Send>,
 ) -> hound::Result<()>
 where
     W: io::Write + io::Seek,
+    C: Canvas<N>,
 {
     let decoder = Decoder::new(image, color_strategy, space_strategy);
 
@@ -195,11 +846,11 @@ impl SampleConvert for i16 {
 
 impl SampleConvert for i32 {
     fn convert_to_i16(self) -> i16 {
-        (self / 2) as i16
+        (self >> 16) as i16
     }
 
     fn convert_to_f32(self) -> f32 {
-        self as f32 / 65536.0
+        self as f32 / 2147483648.0
     }
 }
 
@@ -212,3 +863,372 @@ impl SampleConvert for f32 {
         self
     }
 }
+
+/// Advance a SplitMix64 generator, returning the next pseudo-random value.
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strategy::{
+        color::{
+            CompandedColorStrategy, Companding, GreyColorStrategy,
+            PackedColorStrategy,
+        },
+        space::{
+            HilbertSpaceStrategy, HilbertVolumeSpaceStrategy, LineSpaceStrategy,
+        },
+    };
+
+    /// An encoder over a line long enough for any test.
+    fn line_encoder(
+        samples: Vec<i16>,
+        channels: u16,
+        fill: FillPolicy,
+    ) -> Encoder<i16, std::vec::IntoIter<i16>, 1> {
+        Encoder::new(
+            samples.into_iter(),
+            Box::new(GreyColorStrategy::new(16)),
+            Box::new(LineSpaceStrategy::new(1024)),
+        )
+        .with_channels(channels)
+        .with_fill(fill)
+    }
+
+    /// Encode `samples` along a 4x4 Hilbert curve with `color_strategy`.
+    fn encode_square(
+        samples: &[i16],
+        channels: u16,
+        fill: FillPolicy,
+        alpha: AlphaSource,
+        color_strategy: Box<dyn ColorStrategy + Send>,
+    ) -> RgbaImage {
+        encode_canvas(
+            samples.iter().copied(),
+            channels,
+            fill,
+            alpha,
+            Dither::default(),
+            color_strategy,
+            Box::new(HilbertSpaceStrategy::from_size(4)),
+        )
+    }
+
+    /// A decoder for an image encoded by [`encode_square`].
+    fn decode_square(
+        image: RgbaImage,
+        color_strategy: Box<dyn ColorStrategy + Send>,
+    ) -> Decoder {
+        Decoder::new(
+            image,
+            color_strategy,
+            Box::new(HilbertSpaceStrategy::from_size(4)),
+        )
+    }
+
+    fn grey() -> Box<dyn ColorStrategy + Send> {
+        Box::new(GreyColorStrategy::new(16))
+    }
+
+    fn take_samples<S, I, const N: usize>(
+        encoder: &mut Encoder<S, I, N>,
+        count: usize,
+    ) -> Vec<f32>
+    where
+        S: hound::Sample + SampleConvert,
+        I: Iterator<Item = S>,
+    {
+        (0..count).map_while(|_| encoder.next_sample()).collect()
+    }
+
+    #[test]
+    fn mirror_fill_keeps_channels_in_place() {
+        let samples = [1, 2, 3, 4].map(|x: i16| x << 8).to_vec();
+        let expected = [1, 2, 3, 4, 3, 4, 1, 2, 1, 2, 3, 4, 3, 4]
+            .map(|x: i16| (x << 8).convert_to_f32());
+
+        let mut encoder = line_encoder(samples, 2, FillPolicy::Mirror);
+        assert_eq!(take_samples(&mut encoder, expected.len()), expected);
+    }
+
+    #[test]
+    fn loop_fill_repeats_whole_frames() {
+        let samples = [1, 2, 3, 4, 5].map(|x: i16| x << 8).to_vec();
+        let expected = [1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 1, 2]
+            .map(|x: i16| (x << 8).convert_to_f32());
+
+        let mut encoder = line_encoder(samples, 2, FillPolicy::Loop);
+        assert_eq!(take_samples(&mut encoder, expected.len()), expected);
+    }
+
+    #[test]
+    fn samples_beyond_full_scale_are_clamped() {
+        let mut encoder = Encoder::<f32, _, 1>::new(
+            vec![1.5, -2., 0.5].into_iter(),
+            Box::new(GreyColorStrategy::new(16)),
+            Box::new(LineSpaceStrategy::new(1024)),
+        );
+        assert_eq!(take_samples(&mut encoder, 3), [1., -1., 0.5]);
+    }
+
+    #[test]
+    fn volumes_round_trip() {
+        let samples = (0..512)
+            .map(|i: i32| (i * 127 - 32768) as i16)
+            .collect::<Vec<_>>();
+
+        let volume: Volume = encode_canvas(
+            samples.iter().copied(),
+            1,
+            FillPolicy::Transparent,
+            AlphaSource::Opaque,
+            Dither::default(),
+            Box::new(GreyColorStrategy::new(16)),
+            Box::new(HilbertVolumeSpaceStrategy::from_size(8)),
+        );
+        assert_eq!(volume.lengths(), [8, 8, 8]);
+
+        let decoder = Decoder::new(
+            volume,
+            Box::new(GreyColorStrategy::new(16)),
+            Box::new(HilbertVolumeSpaceStrategy::from_size(8)),
+        );
+        assert_eq!(decoder.collect::<Vec<_>>(), samples);
+    }
+
+    #[test]
+    fn decoding_stops_at_the_length() {
+        let samples = (1..=10).map(|x: i16| x * 1000).collect::<Vec<_>>();
+        let encode = || {
+            encode_square(
+                &samples,
+                1,
+                FillPolicy::Transparent,
+                AlphaSource::Opaque,
+                grey(),
+            )
+        };
+
+        let decoder = decode_square(encode(), grey()).with_length(10);
+        assert_eq!(decoder.collect::<Vec<_>>(), samples);
+
+        // without a length, the padding comes back as silence
+        let decoded = decode_square(encode(), grey()).collect::<Vec<_>>();
+        assert_eq!(decoded.len(), 16);
+        assert_eq!(decoded[..10], samples);
+        assert!(decoded[10..].iter().all(|&sample| sample == 0));
+    }
+
+    #[test]
+    fn transparent_padding_can_be_left_out() {
+        let samples = (1..=10).map(|x: i16| x * 1000).collect::<Vec<_>>();
+        for transparent in
+            [TransparentPixels::EndOfStream, TransparentPixels::Skip]
+        {
+            let image = encode_square(
+                &samples,
+                1,
+                FillPolicy::Transparent,
+                AlphaSource::Opaque,
+                grey(),
+            );
+            let decoder =
+                decode_square(image, grey()).with_transparent(transparent);
+            assert_eq!(decoder.collect::<Vec<_>>(), samples);
+        }
+    }
+
+    #[test]
+    fn fills_cover_the_whole_image() {
+        let samples = [1000, 2000, 3000, 4000, 5000, 6000];
+        let cases = [
+            (FillPolicy::Silence, [0; 10]),
+            (
+                FillPolicy::Loop,
+                [1000, 2000, 3000, 4000, 5000, 6000, 1000, 2000, 3000, 4000],
+            ),
+            (
+                FillPolicy::Mirror,
+                [5000, 6000, 3000, 4000, 1000, 2000, 1000, 2000, 3000, 4000],
+            ),
+        ];
+
+        for (fill, padding) in cases {
+            let image =
+                encode_square(&samples, 2, fill, AlphaSource::Opaque, grey());
+            assert!(image.pixels().all(|pixel| pixel.0[3] == 255));
+
+            let decoder = decode_square(image, grey())
+                .with_transparent(TransparentPixels::EndOfStream);
+            let decoded = decoder.collect::<Vec<_>>();
+            assert_eq!(decoded[..6], samples);
+            assert_eq!(decoded[6..], padding, "{:?}", fill);
+        }
+    }
+
+    #[test]
+    fn alpha_carries_a_second_signal() {
+        let samples = [1000; 16];
+        let second = (0..16).map(|x: i16| (x - 8) * 4096).collect::<Vec<_>>();
+        let encode = || {
+            let alpha =
+                AlphaSource::Signal(Box::new(second.clone().into_iter()));
+            encode_square(&samples, 1, FillPolicy::Transparent, alpha, grey())
+        };
+
+        // the second signal is quantized to 8 bits, and never transparent
+        let decoder =
+            decode_square(encode(), grey()).with_alpha(AlphaMode::Signal);
+        let mut expected = second.clone();
+        expected[0] = alpha_to_sample(1);
+        assert_eq!(decoder.collect::<Vec<_>>(), expected);
+
+        let decoder = decode_square(encode(), grey());
+        assert_eq!(decoder.collect::<Vec<_>>(), samples);
+    }
+
+    #[test]
+    fn alpha_scales_samples_as_gain() {
+        let samples = [16000, -16000, 0, 8000];
+        let image = encode_square(
+            &samples,
+            1,
+            FillPolicy::Transparent,
+            AlphaSource::Envelope { release: 1e6 },
+            grey(),
+        );
+
+        // the envelope holds the first peak, at about half of full scale
+        let alpha = image.pixels().map(|pixel| pixel.0[3]).collect::<Vec<_>>();
+        assert!(alpha.iter().all(|&alpha| alpha == 125 || alpha == 0));
+
+        let decoder = decode_square(image, grey())
+            .with_alpha(AlphaMode::Gain)
+            .with_length(4);
+        let expected =
+            samples.map(|sample| (sample as f32 * 125. / 255.) as i16);
+        for (decoded, expected) in decoder.zip(expected) {
+            assert!((decoded - expected).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn packed_pixels_round_trip() {
+        // the last pixel is only partly filled
+        let samples = (0..29).map(|x: i16| (x - 14) * 2000).collect::<Vec<_>>();
+        let packed = || Box::new(PackedColorStrategy::new(2));
+
+        let image = encode_square(
+            &samples,
+            1,
+            FillPolicy::Transparent,
+            AlphaSource::Opaque,
+            packed(),
+        );
+        let decoder = decode_square(image, packed()).with_length(29);
+        assert_eq!(decoder.collect::<Vec<_>>(), samples);
+    }
+
+    /// The mean of each channel after dithering a constant sample per channel
+    /// to 8-bit grey.
+    fn dithered_means(levels: &[i16], noise_shaping: bool) -> Vec<f32> {
+        let channels = levels.len();
+        let samples = levels.iter().copied().cycle().take(4096);
+        let dither = Dither {
+            step: 256.,
+            noise_shaping,
+        };
+        let grey = || Box::new(GreyColorStrategy::new(8));
+        let curve = || Box::new(HilbertSpaceStrategy::from_size(64));
+
+        let image: RgbaImage = encode_canvas(
+            samples,
+            channels as u16,
+            FillPolicy::Transparent,
+            AlphaSource::Opaque,
+            dither,
+            grey(),
+            curve(),
+        );
+        let decoded = Decoder::new(image, grey(), curve()).collect::<Vec<_>>();
+        (0..channels)
+            .map(|channel| {
+                let samples = decoded.iter().skip(channel).step_by(channels);
+                samples.map(|&sample| sample as f32).sum::<f32>()
+                    / (decoded.len() / channels) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dither_removes_the_quantization_bias() {
+        for noise_shaping in [false, true] {
+            let levels = [100, -1000];
+            let means = dithered_means(&levels, noise_shaping);
+            for (mean, level) in means.into_iter().zip(levels) {
+                assert!(
+                    (mean - level as f32).abs() < 20.,
+                    "{} is dithered to {}",
+                    level,
+                    mean
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn noise_shaping_rejects_packed_samples() {
+        let _ = Encoder::<i16, _, 1>::new(
+            std::iter::empty(),
+            Box::new(PackedColorStrategy::new(2)),
+            Box::new(LineSpaceStrategy::new(16)),
+        )
+        .with_dither(Dither {
+            step: 256.,
+            noise_shaping: true,
+        });
+    }
+
+    #[test]
+    fn float_samples_keep_their_precision() {
+        // quieter than the smallest step of a 16-bit sample
+        let samples = [1e-5, -2e-5, 4e-6, 0.5];
+        let companded = || {
+            Box::new(CompandedColorStrategy::new(
+                GreyColorStrategy::new(16),
+                Companding::MuLaw,
+            ))
+        };
+
+        let image: RgbaImage = encode_canvas(
+            samples.into_iter(),
+            1,
+            FillPolicy::Transparent,
+            AlphaSource::Opaque,
+            Dither::default(),
+            companded(),
+            Box::new(HilbertSpaceStrategy::from_size(2)),
+        );
+        let mut decoder = Decoder::new(
+            image,
+            companded(),
+            Box::new(HilbertSpaceStrategy::from_size(2)),
+        );
+        for sample in samples {
+            let decoded = decoder.next_f32().unwrap();
+            assert!(
+                (decoded - sample).abs() < sample.abs() * 0.01,
+                "{} decodes to {}",
+                sample,
+                decoded
+            );
+        }
+    }
+}
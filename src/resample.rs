@@ -0,0 +1,133 @@
+//! Sample rate conversion of interleaved sample streams.
+
+/// An iterator which converts a stream of interleaved samples from one sample
+/// rate to another using linear interpolation.
+///
+/// The stream ends once there are no longer two full frames to interpolate
+/// between.
+pub struct Resampler<I>
+where
+    I: Iterator<Item = f32>,
+{
+    iter: I,
+    channels: usize,
+    step: f64,
+    position: f64,
+    channel: usize,
+    current: Vec<f32>,
+    next: Vec<f32>,
+}
+
+impl<I> Resampler<I>
+where
+    I: Iterator<Item = f32>,
+{
+    pub fn new(iter: I, channels: u16, from_rate: u32, to_rate: u32) -> Self {
+        assert!(from_rate > 0 && to_rate > 0);
+
+        Self::with_ratio(iter, channels, from_rate as f64 / to_rate as f64)
+    }
+
+    /// Create a resampler which reads `ratio` input frames for every output
+    /// frame, for conversions between rates which are not whole numbers.
+    pub fn with_ratio(mut iter: I, channels: u16, ratio: f64) -> Self {
+        assert!(channels > 0);
+        assert!(ratio > 0. && ratio.is_finite());
+
+        let channels = channels as usize;
+        let current = read_frame(&mut iter, channels);
+        let next = read_frame(&mut iter, channels);
+
+        Resampler {
+            iter,
+            channels,
+            step: ratio,
+            position: 0.,
+            channel: 0,
+            current,
+            next,
+        }
+    }
+}
+
+impl<I> Iterator for Resampler<I>
+where
+    I: Iterator<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.channel == 0 {
+            while self.position >= 1. {
+                self.position -= 1.;
+                self.current = std::mem::replace(
+                    &mut self.next,
+                    read_frame(&mut self.iter, self.channels),
+                );
+            }
+        }
+
+        if self.next.len() < self.channels {
+            return None;
+        }
+
+        let from = self.current[self.channel];
+        let to = self.next[self.channel];
+        let sample = from + (to - from) * self.position as f32;
+
+        self.channel += 1;
+        if self.channel == self.channels {
+            self.channel = 0;
+            self.position += self.step;
+        }
+
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let buffered = self.current.len() + self.next.len();
+        let upper = self.iter.size_hint().1.map(|n| {
+            ((n + buffered) as f64 / self.step).ceil() as usize + self.channels
+        });
+        (0, upper)
+    }
+}
+
+fn read_frame<I>(iter: &mut I, channels: usize) -> Vec<f32>
+where
+    I: Iterator<Item = f32>,
+{
+    iter.take(channels).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_stay_in_place() {
+        // a rising left channel and a falling right one, which ends on the
+        // last frame that can be interpolated towards
+        let samples = (0..8).flat_map(|i| [i as f32, -(i as f32)]);
+        let output = Resampler::new(samples, 2, 1, 2).collect::<Vec<_>>();
+
+        let left = output.iter().step_by(2).copied().collect::<Vec<_>>();
+        let right = output.iter().skip(1).step_by(2).copied();
+        assert_eq!(left, (0..14).map(|i| i as f32 / 2.).collect::<Vec<_>>());
+        assert!(right.zip(&left).all(|(right, &left)| right == -left));
+    }
+
+    #[test]
+    fn downsampling_skips_frames() {
+        let samples = (0..9).map(|i| i as f32);
+        let output = Resampler::new(samples, 1, 3, 1).collect::<Vec<_>>();
+        assert_eq!(output, [0., 3., 6.]);
+    }
+
+    #[test]
+    fn ratios_need_not_be_whole_rates() {
+        let samples = (0..8).map(|i| i as f32);
+        let output = Resampler::with_ratio(samples, 1, 2.5).collect::<Vec<_>>();
+        assert_eq!(output, [0., 2.5, 5.]);
+    }
+}
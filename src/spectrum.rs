@@ -0,0 +1,285 @@
+//! Conversion between audio and its spectrum, so that audio can be encoded in
+//! the frequency domain with a [`ComplexColorStrategy`].
+//!
+//! The audio is split into frames of a fixed size, each of which is
+//! transformed on its own with a rectangular window, so that the transform is
+//! exactly invertible. Since audio is real, a frame of `n` samples is described
+//! by `n / 2` complex bins, where the first bin holds the components at zero
+//! and at the Nyquist frequency, which are both real, as its real and
+//! imaginary parts.
+//!
+//! Each channel is transformed separately. This is only available through the
+//! library for now, and not through any command or preset.
+
+use dsp::{
+    node::fft::{ForwardFFT, InverseFFT, WindowType},
+    num_complex::Complex32,
+    runtime::ProcessNode,
+};
+
+use crate::{
+    decode_complex_canvas, encode_complex_canvas,
+    strategy::{ComplexColorStrategy, SpaceStrategy},
+    Canvas, SizeMismatch,
+};
+
+/// A transform between frames of samples and their spectra.
+pub struct Spectrum {
+    frame_size: usize,
+    forward: ForwardFFT,
+    inverse: InverseFFT,
+}
+
+impl Spectrum {
+    pub fn new(frame_size: usize) -> Self {
+        assert!(
+            frame_size >= 2 && frame_size.is_power_of_two(),
+            "frame size must be a power of two"
+        );
+
+        Spectrum {
+            frame_size,
+            forward: ForwardFFT::new(frame_size, WindowType::Rectangular),
+            inverse: InverseFFT::new(frame_size),
+        }
+    }
+
+    /// The number of samples in each frame.
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// The number of bins describing each frame.
+    pub fn bins(&self) -> usize {
+        self.frame_size / 2
+    }
+
+    /// Transform a frame of samples into its bins, scaled so that a full-scale
+    /// sine wave has a magnitude of one.
+    ///
+    /// A frame shorter than the frame size is padded with silence.
+    pub fn forward(&mut self, frame: &[i16]) -> Vec<Complex32> {
+        let mut input = vec![Complex32::default(); self.frame_size];
+        for (value, &sample) in input.iter_mut().zip(frame) {
+            value.re = sample as f32 / 32768.;
+        }
+
+        let mut output = vec![Complex32::default(); self.frame_size];
+        self.forward
+            .process_buffer(&input, &mut output)
+            .expect("could not transform frame");
+
+        let scale = 2. / self.frame_size as f32;
+        let mut bins = output[..self.bins()]
+            .iter()
+            .map(|bin| bin * scale)
+            .collect::<Vec<_>>();
+        bins[0].im = output[self.bins()].re * scale;
+        bins
+    }
+
+    /// The inverse of [`Spectrum::forward`].
+    pub fn inverse(&mut self, bins: &[Complex32]) -> Vec<i16> {
+        assert!(bins.len() == self.bins());
+
+        // rebuild the full, conjugate symmetric spectrum
+        let scale = self.frame_size as f32 / 2.;
+        let mut input = vec![Complex32::default(); self.frame_size];
+        input[0] = Complex32::new(bins[0].re, 0.) * scale;
+        input[self.bins()] = Complex32::new(bins[0].im, 0.) * scale;
+        for k in 1..self.bins() {
+            input[k] = bins[k] * scale;
+            input[self.frame_size - k] = bins[k].conj() * scale;
+        }
+
+        let mut output = vec![Complex32::default(); self.frame_size];
+        self.inverse
+            .process_buffer(&input, &mut output)
+            .expect("could not transform frame");
+
+        output
+            .iter()
+            .map(|value| {
+                (value.re / self.frame_size as f32 * 32768.)
+                    .round()
+                    .clamp(i16::MIN as f32, i16::MAX as f32)
+                    as i16
+            })
+            .collect()
+    }
+}
+
+/// Encode a canvas from the spectrum of a stream of interleaved samples with
+/// the given number of channels. The bins of each channel of a frame follow
+/// each other along the space strategy, frame after frame, for as many whole
+/// frames as fit.
+///
+/// Also returns the number of samples which were encoded, which
+/// [`decode_spectrum`] needs to drop the silence padding out the last frame.
+pub fn encode_spectrum<I, C, const N: usize>(
+    iter: I,
+    channels: u16,
+    frame_size: usize,
+    color_strategy: Box<dyn ComplexColorStrategy + Send>,
+    space_strategy: Box<dyn SpaceStrategy<N> + Send>,
+) -> (C, usize)
+where
+    I: Iterator<Item = i16>,
+    C: Canvas<N>,
+{
+    let channels = channels as usize;
+    let mut spectrum = Spectrum::new(frame_size);
+    let frames = space_strategy.size() / (spectrum.bins() * channels);
+    let samples = iter.collect::<Vec<_>>();
+    let length = samples.len().min(frames * frame_size * channels);
+
+    let mut frame = vec![0; frame_size];
+    let mut bins = Vec::new();
+    for interleaved in samples[..length].chunks(frame_size * channels) {
+        for channel in 0..channels {
+            for (i, sample) in frame.iter_mut().enumerate() {
+                *sample = interleaved
+                    .get(i * channels + channel)
+                    .copied()
+                    .unwrap_or(0);
+            }
+            bins.extend(spectrum.forward(&frame));
+        }
+    }
+
+    let canvas =
+        encode_complex_canvas(bins.into_iter(), color_strategy, space_strategy);
+    (canvas, length)
+}
+
+/// Decode the interleaved samples of a canvas encoded by [`encode_spectrum`]
+/// with the same number of channels, stopping after `length` samples if it is
+/// given, or after the last whole frame otherwise.
+pub fn decode_spectrum<C, const N: usize>(
+    canvas: &C,
+    channels: u16,
+    frame_size: usize,
+    length: Option<usize>,
+    color_strategy: Box<dyn ComplexColorStrategy + Send>,
+    space_strategy: Box<dyn SpaceStrategy<N> + Send>,
+) -> Result<Vec<i16>, SizeMismatch>
+where
+    C: Canvas<N>,
+{
+    let channels = channels as usize;
+    let mut spectrum = Spectrum::new(frame_size);
+    let bins = spectrum.bins();
+
+    let mut samples = Vec::new();
+    let values = decode_complex_canvas(canvas, color_strategy, space_strategy)?;
+    for frame in values.chunks_exact(bins * channels) {
+        let frame = frame
+            .chunks_exact(bins)
+            .map(|bins| spectrum.inverse(bins))
+            .collect::<Vec<_>>();
+        for i in 0..frame_size {
+            samples.extend(frame.iter().map(|channel| channel[i]));
+        }
+    }
+
+    if let Some(length) = length {
+        samples.truncate(length);
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use nannou::image::RgbaImage;
+
+    use super::*;
+    use crate::strategy::{
+        complex::DomainColorStrategy, space::HilbertSpaceStrategy,
+    };
+
+    fn tone(length: usize, cycles: f32, amplitude: f32) -> Vec<i16> {
+        (0..length)
+            .map(|i| {
+                let phase = TAU * cycles * i as f32 / length as f32;
+                (amplitude * phase.sin() * 32767.).round() as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn transform_round_trips() {
+        let mut spectrum = Spectrum::new(256);
+        let frame = tone(256, 5., 0.5)
+            .into_iter()
+            .zip(tone(256, 31., 0.25))
+            .map(|(a, b)| a + b)
+            .collect::<Vec<_>>();
+
+        let bins = spectrum.forward(&frame);
+        assert_eq!(bins.len(), 128);
+        assert!((bins[5].norm() - 0.5).abs() < 1e-3);
+        assert!((bins[31].norm() - 0.25).abs() < 1e-3);
+
+        let output = spectrum.inverse(&bins);
+        for (a, b) in frame.iter().zip(&output) {
+            assert!((a - b).abs() <= 1, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn channels_are_transformed_separately() {
+        // a silent left channel and a tone in the right one, with the last
+        // frame only partly filled
+        let right = tone(1000, 40., 0.5);
+        let samples = right
+            .iter()
+            .flat_map(|&sample| [0, sample])
+            .collect::<Vec<_>>();
+
+        let strategies = || {
+            (
+                Box::new(DomainColorStrategy::new(0.05)),
+                Box::new(HilbertSpaceStrategy::from_size(64)),
+            )
+        };
+        let (color, space) = strategies();
+        let (canvas, length): (RgbaImage, _) =
+            encode_spectrum(samples.iter().copied(), 2, 256, color, space);
+        assert_eq!(length, samples.len());
+
+        let (color, space) = strategies();
+        let output =
+            decode_spectrum(&canvas, 2, 256, Some(length), color, space)
+                .unwrap();
+        assert_eq!(output.len(), samples.len());
+
+        let error = |channel: usize| {
+            let squares = output
+                .iter()
+                .zip(&samples)
+                .skip(channel)
+                .step_by(2)
+                .map(|(&a, &b)| (a as f32 - b as f32).powi(2))
+                .sum::<f32>();
+            (squares / right.len() as f32).sqrt() / 32768.
+        };
+        assert!(error(0) < 0.01, "left error {}", error(0));
+        assert!(error(1) < 0.05, "right error {}", error(1));
+    }
+
+    #[test]
+    fn size_mismatch_is_an_error() {
+        let canvas = RgbaImage::new(32, 32);
+        let result = decode_spectrum(
+            &canvas,
+            1,
+            256,
+            None,
+            Box::new(DomainColorStrategy::new(0.05)),
+            Box::new(HilbertSpaceStrategy::from_size(64)),
+        );
+        assert!(result.is_err());
+    }
+}
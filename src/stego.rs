@@ -0,0 +1,294 @@
+//! Steganographic hiding of audio inside of an existing cover image.
+//!
+//! Samples are written with a [`LsbColorStrategy`] into the pixels of the
+//! cover, in an order given by a [`PixelOrder`]. The first pixels in that order
+//! hold a [`Header`] describing the hidden audio, so that it can be revealed
+//! without knowing its length in advance.
+
+use std::{collections::VecDeque, error, fmt};
+
+use nannou::image::RgbaImage;
+
+use crate::{
+    splitmix64,
+    strategy::{
+        color::LsbColorStrategy, space::HilbertSpaceStrategy, ColorStrategy,
+        SpaceStrategy,
+    },
+    Coord,
+};
+
+/// A marker at the start of the header, used to detect whether an image holds
+/// hidden audio at all (or whether the wrong key was used).
+const MAGIC: u32 = 0x4853;
+
+/// The number of bits in an encoded [`Header`].
+const HEADER_BITS: u32 = 16 + 32 + 32 + 16;
+
+/// The order in which the pixels of a cover image are visited.
+#[derive(Clone, Copy, Debug)]
+pub enum PixelOrder {
+    /// Follow a Hilbert curve large enough to cover the image, skipping any
+    /// points which lie outside of it.
+    Hilbert,
+    /// Visit the pixels in a pseudo-random order determined by a key.
+    Keyed(u64),
+}
+
+impl PixelOrder {
+    /// The coordinates of every pixel in an image of the given size, in order.
+    pub fn coords(&self, width: u32, height: u32) -> Vec<Coord<2>> {
+        match self {
+            PixelOrder::Hilbert => {
+                let side = width.max(height).next_power_of_two();
+                let strategy = HilbertSpaceStrategy::from_size(side);
+                (0..strategy.size())
+                    .filter_map(|index| strategy.index_to_coord(index))
+                    .filter(|&[x, y]| x < width && y < height)
+                    .collect()
+            }
+            PixelOrder::Keyed(key) => {
+                let mut coords: Vec<_> = (0..height)
+                    .flat_map(|y| (0..width).map(move |x| [x, y]))
+                    .collect();
+
+                // Fisher-Yates shuffle
+                let mut state = *key;
+                for idx in (1..coords.len()).rev() {
+                    let other = splitmix64(&mut state) % (idx as u64 + 1);
+                    coords.swap(idx, other as usize);
+                }
+
+                coords
+            }
+        }
+    }
+}
+
+/// A description of the audio hidden in an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// The number of hidden samples.
+    pub length: u32,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// An error which occurred while hiding or revealing audio.
+#[derive(Debug)]
+pub enum StegoError {
+    /// The cover image is too small to hold the audio.
+    Capacity { required: usize, available: usize },
+    /// The image does not contain a valid header.
+    MissingHeader,
+}
+
+impl fmt::Display for StegoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StegoError::Capacity {
+                required,
+                available,
+            } => write!(
+                f,
+                "cover image can hold {} samples, but {} are required",
+                available, required
+            ),
+            StegoError::MissingHeader => {
+                write!(f, "image does not contain hidden audio")
+            }
+        }
+    }
+}
+
+impl error::Error for StegoError {}
+
+/// The number of pixels needed to store the header.
+fn header_pixels(strategy: &LsbColorStrategy) -> usize {
+    HEADER_BITS.div_ceil(strategy.payload_bits()) as usize
+}
+
+/// The number of samples which can be hidden in a cover image of the given
+/// size.
+pub fn capacity(width: u32, height: u32, strategy: &LsbColorStrategy) -> usize {
+    (width as usize * height as usize).saturating_sub(header_pixels(strategy))
+}
+
+/// Hide `samples` inside of `cover`.
+///
+/// The capacity of the cover is checked before any pixel is modified.
+pub fn hide(
+    cover: &mut RgbaImage,
+    samples: &[i16],
+    sample_rate: u32,
+    channels: u16,
+    strategy: &LsbColorStrategy,
+    order: PixelOrder,
+) -> Result<(), StegoError> {
+    let available = capacity(cover.width(), cover.height(), strategy);
+    if samples.len() > available || samples.len() > u32::MAX as usize {
+        return Err(StegoError::Capacity {
+            required: samples.len(),
+            available,
+        });
+    }
+
+    let header = Header {
+        length: samples.len() as u32,
+        sample_rate,
+        channels,
+    };
+
+    let coords = order.coords(cover.width(), cover.height());
+    let (header_coords, sample_coords) =
+        coords.split_at(header_pixels(strategy));
+
+    let mut bits = header_to_bits(&header);
+    for &[x, y] in header_coords {
+        let value = take_bits(&mut bits, strategy.payload_bits());
+        let pixel = strategy.embed_bits(value, cover.get_pixel(x, y));
+        cover.put_pixel(x, y, pixel);
+    }
+
+    for (&sample, &[x, y]) in samples.iter().zip(sample_coords) {
+        let pixel = strategy.embed_sample(sample, cover.get_pixel(x, y));
+        cover.put_pixel(x, y, pixel);
+    }
+
+    Ok(())
+}
+
+/// Reveal the audio hidden inside of `image` by [`hide`].
+pub fn reveal(
+    image: &RgbaImage,
+    strategy: &LsbColorStrategy,
+    order: PixelOrder,
+) -> Result<(Header, Vec<i16>), StegoError> {
+    let coords = order.coords(image.width(), image.height());
+    if coords.len() < header_pixels(strategy) {
+        return Err(StegoError::MissingHeader);
+    }
+    let (header_coords, sample_coords) =
+        coords.split_at(header_pixels(strategy));
+
+    let mut bits = VecDeque::with_capacity(HEADER_BITS as usize);
+    for &[x, y] in header_coords {
+        let value = strategy.extract_bits(image.get_pixel(x, y));
+        put_bits(&mut bits, value, strategy.payload_bits());
+    }
+    let header = bits_to_header(&mut bits).ok_or(StegoError::MissingHeader)?;
+
+    if header.length as usize > sample_coords.len() {
+        return Err(StegoError::MissingHeader);
+    }
+
+    let samples = sample_coords[..header.length as usize]
+        .iter()
+        .map(|&[x, y]| strategy.color_to_sample(image.get_pixel(x, y)))
+        .collect();
+
+    Ok((header, samples))
+}
+
+/// Serialize a header into a sequence of bits, most significant first.
+fn header_to_bits(header: &Header) -> VecDeque<bool> {
+    let mut bits = VecDeque::with_capacity(HEADER_BITS as usize);
+    put_bits(&mut bits, MAGIC, 16);
+    put_bits(&mut bits, header.length, 32);
+    put_bits(&mut bits, header.sample_rate, 32);
+    put_bits(&mut bits, header.channels as u32, 16);
+    bits
+}
+
+fn bits_to_header(bits: &mut VecDeque<bool>) -> Option<Header> {
+    if take_bits(bits, 16) != MAGIC {
+        return None;
+    }
+    Some(Header {
+        length: take_bits(bits, 32),
+        sample_rate: take_bits(bits, 32),
+        channels: take_bits(bits, 16) as u16,
+    })
+}
+
+/// Append the lowest `count` bits of `value`, most significant first.
+fn put_bits(bits: &mut VecDeque<bool>, value: u32, count: u32) {
+    bits.extend((0..count).rev().map(|shift| (value >> shift) & 1 == 1));
+}
+
+/// Take `count` bits from the front of `bits`, padding with zeros once empty.
+fn take_bits(bits: &mut VecDeque<bool>, count: u32) -> u32 {
+    (0..count).fold(0, |value, _| {
+        (value << 1) | bits.pop_front().unwrap_or(false) as u32
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nannou::image;
+
+    use super::*;
+
+    fn cover() -> RgbaImage {
+        RgbaImage::from_fn(20, 12, |x, y| {
+            image::Rgba([(x * 12) as u8, (y * 20) as u8, 200, 255])
+        })
+    }
+
+    #[test]
+    fn hidden_audio_is_revealed() {
+        // 18 bits per pixel hold a whole sample
+        let strategy = LsbColorStrategy::new(6);
+        let samples = (0..200)
+            .map(|i: i32| (i * 331 - 32768) as i16)
+            .collect::<Vec<_>>();
+
+        for order in [PixelOrder::Hilbert, PixelOrder::Keyed(7)] {
+            let mut image = cover();
+            hide(&mut image, &samples, 44100, 2, &strategy, order).unwrap();
+
+            // only the hidden bits of the cover change
+            for (hidden, original) in image.pixels().zip(cover().pixels()) {
+                for (hidden, original) in hidden.0.iter().zip(original.0) {
+                    assert_eq!(hidden >> 6, original >> 6);
+                }
+            }
+
+            let (header, revealed) = reveal(&image, &strategy, order).unwrap();
+            assert_eq!(
+                header,
+                Header {
+                    length: 200,
+                    sample_rate: 44100,
+                    channels: 2,
+                }
+            );
+            assert_eq!(revealed, samples);
+        }
+    }
+
+    #[test]
+    fn capacity_is_checked() {
+        let strategy = LsbColorStrategy::new(2);
+        let available = capacity(20, 12, &strategy);
+        assert_eq!(available, 20 * 12 - 16);
+
+        let samples = vec![0; available + 1];
+        let result = hide(
+            &mut cover(),
+            &samples,
+            44100,
+            1,
+            &strategy,
+            PixelOrder::Hilbert,
+        );
+        assert!(matches!(result, Err(StegoError::Capacity { .. })));
+    }
+
+    #[test]
+    fn a_plain_image_has_no_header() {
+        let strategy = LsbColorStrategy::new(2);
+        let result = reveal(&cover(), &strategy, PixelOrder::Keyed(1));
+        assert!(matches!(result, Err(StegoError::MissingHeader)));
+    }
+}
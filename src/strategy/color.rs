@@ -1,10 +1,26 @@
 //! Mapping strategies between colors and samples.
 
+use std::{cell::RefCell, collections::HashMap, f32::consts::PI};
+
 use nannou::{
-    color::{hsv, Hsv, Rgb},
+    color::{hsv, Hsv, Lab, LinSrgb, Rgb},
     image,
 };
 
+use crate::SampleConvert;
+
+/// How images of the colors of a [`ColorStrategy`] are stored, so that no
+/// precision is lost.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 8 bits for each of red, green, blue and alpha.
+    #[default]
+    Rgba8,
+    /// A 16-bit grey with alpha, packed into colors as by
+    /// [`GreyColorStrategy::from_luma16`].
+    LumaA16,
+}
+
 /// A color strategy which represents a mapping between sound samples and
 /// colors.
 ///
@@ -31,6 +47,180 @@ pub trait ColorStrategy {
     ///
     /// This should accept any value for `color` without panicking.
     fn color_to_sample(&self, color: &image::Rgba<u8>) -> i16;
+
+    /// Convert a sample to a color by modifying an existing `cover` color.
+    ///
+    /// By default, the cover is ignored and this is equivalent to
+    /// [`ColorStrategy::sample_to_color`].
+    fn embed_sample(
+        &self,
+        sample: i16,
+        cover: &image::Rgba<u8>,
+    ) -> image::Rgba<u8> {
+        let _ = cover;
+        self.sample_to_color(sample)
+    }
+
+    /// The number of consecutive samples packed into each color.
+    ///
+    /// By default, this is one.
+    fn samples_per_pixel(&self) -> usize {
+        1
+    }
+
+    /// Whether colors use the alpha channel to hold samples, in which case it
+    /// is left alone by an [`Encoder`](crate::Encoder) and
+    /// [`Decoder`](crate::Decoder).
+    fn uses_alpha(&self) -> bool {
+        false
+    }
+
+    /// The format in which images of these colors should be stored.
+    ///
+    /// By default, this is [`PixelFormat::Rgba8`].
+    fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::Rgba8
+    }
+
+    /// Convert a sample between -1 and 1 to a color, for strategies which
+    /// can keep more precision than an [`i16`].
+    ///
+    /// By default, the sample is converted to an [`i16`] and passed to
+    /// [`ColorStrategy::sample_to_color`].
+    fn sample_to_color_f32(&self, sample: f32) -> image::Rgba<u8> {
+        self.sample_to_color(sample.convert_to_i16())
+    }
+
+    /// Convert a color to a sample between -1 and 1, for strategies which
+    /// can keep more precision than an [`i16`].
+    ///
+    /// By default, this is the result of [`ColorStrategy::color_to_sample`].
+    fn color_to_sample_f32(&self, color: &image::Rgba<u8>) -> f32 {
+        self.color_to_sample(color).convert_to_f32()
+    }
+
+    /// Convert [`ColorStrategy::samples_per_pixel`] samples to a color.
+    ///
+    /// By default, this is equivalent to [`ColorStrategy::sample_to_color`]
+    /// on the first sample.
+    fn samples_to_color(&self, samples: &[i16]) -> image::Rgba<u8> {
+        self.sample_to_color(samples[0])
+    }
+
+    /// Convert a color to [`ColorStrategy::samples_per_pixel`] samples.
+    ///
+    /// By default, this is equivalent to [`ColorStrategy::color_to_sample`]
+    /// for the first sample.
+    fn color_to_samples(&self, color: &image::Rgba<u8>, samples: &mut [i16]) {
+        samples[0] = self.color_to_sample(color);
+    }
+
+    /// The number of samples on either side of each sample which
+    /// [`ColorStrategy::sample_to_color_in_context`] looks at.
+    ///
+    /// By default, this is zero.
+    fn context(&self) -> usize {
+        0
+    }
+
+    /// Convert the sample at `center` of a window of samples between -1 and 1
+    /// to a color, for strategies which look at the samples around it.
+    ///
+    /// The window holds up to [`ColorStrategy::context`] samples on either
+    /// side of the sample, and fewer at the start and end of the audio. By
+    /// default, this is equivalent to [`ColorStrategy::sample_to_color_f32`]
+    /// on the sample.
+    fn sample_to_color_in_context(
+        &self,
+        window: &[f32],
+        center: usize,
+    ) -> image::Rgba<u8> {
+        self.sample_to_color_f32(window[center])
+    }
+
+    /// Convert a color to a sample between -1 and 1, for strategies which
+    /// look at the samples around each one and carry `state` from one sample
+    /// of a channel to the next, such as a phase.
+    ///
+    /// The state of each channel starts at zero. By default, it is unused and
+    /// this is equivalent to [`ColorStrategy::color_to_sample_f32`].
+    fn color_to_sample_in_context(
+        &self,
+        color: &image::Rgba<u8>,
+        state: &mut f32,
+    ) -> f32 {
+        let _ = state;
+        self.color_to_sample_f32(color)
+    }
+}
+
+impl<C> ColorStrategy for Box<C>
+where
+    C: ColorStrategy + ?Sized,
+{
+    fn sample_to_color(&self, sample: i16) -> image::Rgba<u8> {
+        (**self).sample_to_color(sample)
+    }
+
+    fn color_to_sample(&self, color: &image::Rgba<u8>) -> i16 {
+        (**self).color_to_sample(color)
+    }
+
+    fn embed_sample(
+        &self,
+        sample: i16,
+        cover: &image::Rgba<u8>,
+    ) -> image::Rgba<u8> {
+        (**self).embed_sample(sample, cover)
+    }
+
+    fn samples_per_pixel(&self) -> usize {
+        (**self).samples_per_pixel()
+    }
+
+    fn uses_alpha(&self) -> bool {
+        (**self).uses_alpha()
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        (**self).pixel_format()
+    }
+
+    fn sample_to_color_f32(&self, sample: f32) -> image::Rgba<u8> {
+        (**self).sample_to_color_f32(sample)
+    }
+
+    fn color_to_sample_f32(&self, color: &image::Rgba<u8>) -> f32 {
+        (**self).color_to_sample_f32(color)
+    }
+
+    fn samples_to_color(&self, samples: &[i16]) -> image::Rgba<u8> {
+        (**self).samples_to_color(samples)
+    }
+
+    fn color_to_samples(&self, color: &image::Rgba<u8>, samples: &mut [i16]) {
+        (**self).color_to_samples(color, samples)
+    }
+
+    fn context(&self) -> usize {
+        (**self).context()
+    }
+
+    fn sample_to_color_in_context(
+        &self,
+        window: &[f32],
+        center: usize,
+    ) -> image::Rgba<u8> {
+        (**self).sample_to_color_in_context(window, center)
+    }
+
+    fn color_to_sample_in_context(
+        &self,
+        color: &image::Rgba<u8>,
+        state: &mut f32,
+    ) -> f32 {
+        (**self).color_to_sample_in_context(color, state)
+    }
 }
 
 /// A [`ColorStrategy`] which maps operates based on hue.
@@ -47,7 +237,15 @@ impl HueColorStrategy {
 
 impl ColorStrategy for HueColorStrategy {
     fn sample_to_color(&self, sample: i16) -> image::Rgba<u8> {
-        let hue = (sample as f32 + 2u32.pow(15) as f32) / (2u32.pow(16) as f32);
+        self.sample_to_color_f32(sample.convert_to_f32())
+    }
+
+    fn color_to_sample(&self, color: &image::Rgba<u8>) -> i16 {
+        self.color_to_sample_f32(color).convert_to_i16()
+    }
+
+    fn sample_to_color_f32(&self, sample: f32) -> image::Rgba<u8> {
+        let hue = (sample + 1.) / 2.;
         let rgb: Rgb = hsv(hue, self.saturation, self.value).into();
         image::Rgba([
             (255. * rgb.red) as u8,
@@ -57,13 +255,1297 @@ impl ColorStrategy for HueColorStrategy {
         ])
     }
 
+    fn color_to_sample_f32(&self, color: &image::Rgba<u8>) -> f32 {
+        let [r, g, b, _] = color.0;
+        let hsv: Hsv =
+            Rgb::new(r as f32 / 255., g as f32 / 255., b as f32 / 255.).into();
+        hsv.hue.to_positive_radians() / PI - 1.
+    }
+}
+
+/// A [`ColorStrategy`] which maps samples to shades of grey, from black at the
+/// most negative sample to white at the most positive.
+///
+/// With 8 bits, only the most significant bits of each sample are kept, and
+/// colors are decoded from their luma. With 16 bits, the red and blue channels
+/// hold the most significant byte of each sample and the green channel holds
+/// the least significant byte, which [`GreyColorStrategy::to_luma16`] turns
+/// into a 16-bit grey for saving as [`PixelFormat::LumaA16`]. Since every byte
+/// of an 8-bit grey repeats in this packing, 8-bit grey images still decode as
+/// if they were scaled up to 16 bits.
+pub struct GreyColorStrategy {
+    bits: u32,
+}
+
+impl GreyColorStrategy {
+    pub fn new(bits: u32) -> Self {
+        assert!(bits == 8 || bits == 16, "grey must have 8 or 16 bits");
+
+        GreyColorStrategy { bits }
+    }
+
+    /// The number of bits of each sample which are kept.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Unpack a color written with 16 bits into a 16-bit grey with alpha.
+    pub fn to_luma16(color: &image::Rgba<u8>) -> image::LumaA<u16> {
+        let [r, g, _, a] = color.0;
+        image::LumaA([u16::from_be_bytes([r, g]), a as u16 * 257])
+    }
+
+    /// The inverse of [`GreyColorStrategy::to_luma16`].
+    pub fn from_luma16(luma: &image::LumaA<u16>) -> image::Rgba<u8> {
+        let [l, a] = luma.0;
+        let [high, low] = l.to_be_bytes();
+        image::Rgba([high, low, high, ((a as u32 + 128) / 257) as u8])
+    }
+}
+
+impl ColorStrategy for GreyColorStrategy {
+    fn sample_to_color(&self, sample: i16) -> image::Rgba<u8> {
+        let unsigned = (sample as i32 + 2i32.pow(15)) as u32;
+        if self.bits == 16 {
+            Self::from_luma16(&image::LumaA([unsigned as u16, u16::MAX]))
+        } else {
+            let grey = ((unsigned + 128) / 257) as u8;
+            image::Rgba([grey, grey, grey, 255])
+        }
+    }
+
+    fn color_to_sample(&self, color: &image::Rgba<u8>) -> i16 {
+        let unsigned = if self.bits == 16 {
+            Self::to_luma16(color).0[0] as u32
+        } else {
+            // Rec. 709 luma, as image editors use for desaturating
+            let [r, g, b, _] = color.0.map(|c| c as u32);
+            (2126 * r + 7152 * g + 722 * b + 5000) / 10000 * 257
+        };
+        (unsigned as i32 - 2i32.pow(15)) as i16
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        if self.bits == 16 {
+            PixelFormat::LumaA16
+        } else {
+            PixelFormat::Rgba8
+        }
+    }
+}
+
+/// A built-in, perceptually uniform colormap for [`ColormapColorStrategy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+}
+
+impl Colormap {
+    /// Evenly spaced stops along the colormap.
+    pub fn stops(&self) -> &'static [[u8; 3]] {
+        match self {
+            Colormap::Viridis => &[
+                [0x44, 0x01, 0x54],
+                [0x48, 0x24, 0x75],
+                [0x41, 0x44, 0x87],
+                [0x35, 0x5f, 0x8d],
+                [0x2a, 0x78, 0x8e],
+                [0x21, 0x91, 0x8c],
+                [0x22, 0xa8, 0x84],
+                [0x44, 0xbf, 0x70],
+                [0x7a, 0xd1, 0x51],
+                [0xbd, 0xdf, 0x26],
+                [0xfd, 0xe7, 0x25],
+            ],
+            Colormap::Magma => &[
+                [0x00, 0x00, 0x04],
+                [0x14, 0x0e, 0x36],
+                [0x3b, 0x0f, 0x70],
+                [0x64, 0x1a, 0x80],
+                [0x8c, 0x29, 0x81],
+                [0xb7, 0x37, 0x79],
+                [0xde, 0x49, 0x68],
+                [0xf7, 0x70, 0x5c],
+                [0xfe, 0x9f, 0x6d],
+                [0xfe, 0xcf, 0x92],
+                [0xfc, 0xfd, 0xbf],
+            ],
+        }
+    }
+}
+
+/// A [`ColorStrategy`] which maps samples along a gradient, from the most
+/// negative sample at the first stop to the most positive at the last.
+///
+/// Unlike [`HueColorStrategy`], the gradient does not wrap around. Colors are
+/// inverted by finding the nearest point on the gradient, so colors which are
+/// blended between two points on the gradient decode to a sample between
+/// them.
+pub struct ColormapColorStrategy {
+    stops: Vec<[f32; 3]>,
+}
+
+impl ColormapColorStrategy {
+    /// Create a strategy from evenly spaced gradient stops.
+    pub fn new(stops: &[[u8; 3]]) -> Self {
+        assert!(stops.len() >= 2, "a gradient needs at least two stops");
+
+        ColormapColorStrategy {
+            stops: stops.iter().map(|stop| stop.map(|c| c as f32)).collect(),
+        }
+    }
+
+    pub fn from_colormap(colormap: Colormap) -> Self {
+        Self::new(colormap.stops())
+    }
+
+    /// The number of segments between stops.
+    fn segments(&self) -> usize {
+        self.stops.len() - 1
+    }
+}
+
+impl ColorStrategy for ColormapColorStrategy {
+    fn sample_to_color(&self, sample: i16) -> image::Rgba<u8> {
+        let position = (sample as f32 + 2u32.pow(15) as f32)
+            / (u16::MAX as f32)
+            * self.segments() as f32;
+        let segment = (position as usize).min(self.segments() - 1);
+        let t = position - segment as f32;
+
+        let (from, to) = (self.stops[segment], self.stops[segment + 1]);
+        let [r, g, b] = [0, 1, 2].map(|c| from[c] + (to[c] - from[c]) * t);
+        image::Rgba([r.round() as u8, g.round() as u8, b.round() as u8, 255])
+    }
+
+    fn color_to_sample(&self, color: &image::Rgba<u8>) -> i16 {
+        let [r, g, b, _] = color.0;
+        let color = [r as f32, g as f32, b as f32];
+
+        // project the color onto each segment and keep the closest point
+        let (_, position) = self
+            .stops
+            .windows(2)
+            .enumerate()
+            .map(|(segment, stops)| {
+                let (from, to) = (stops[0], stops[1]);
+                let direction = [0, 1, 2].map(|c| to[c] - from[c]);
+                let offset = [0, 1, 2].map(|c| color[c] - from[c]);
+                let length = dot(direction, direction);
+                let t = if length > 0. {
+                    (dot(offset, direction) / length).clamp(0., 1.)
+                } else {
+                    0.
+                };
+                let nearest = [0, 1, 2].map(|c| from[c] + direction[c] * t);
+                let error = [0, 1, 2].map(|c| color[c] - nearest[c]);
+                (dot(error, error), segment as f32 + t)
+            })
+            .fold((f32::INFINITY, 0.), |best, candidate| {
+                if candidate.0 < best.0 {
+                    candidate
+                } else {
+                    best
+                }
+            });
+
+        let sample = position / self.segments() as f32 * (u16::MAX as f32)
+            - 2u32.pow(15) as f32;
+        sample.round() as i16
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// A perceptual color space for [`LightnessColorStrategy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightnessSpace {
+    Oklab,
+    Cielab,
+}
+
+impl LightnessSpace {
+    /// The lightness of white.
+    fn white(&self) -> f32 {
+        match self {
+            LightnessSpace::Oklab => 1.,
+            LightnessSpace::Cielab => 100.,
+        }
+    }
+
+    /// Convert a lightness and the opponent axes `a` and `b` to sRGB, without
+    /// clamping to the gamut.
+    #[allow(clippy::excessive_precision)]
+    fn to_rgb(self, lightness: f32, a: f32, b: f32) -> [f32; 3] {
+        match self {
+            LightnessSpace::Oklab => {
+                let l = lightness + 0.3963377774 * a + 0.2158037573 * b;
+                let m = lightness - 0.1055613458 * a - 0.0638541728 * b;
+                let s = lightness - 0.0894841775 * a - 1.2914855480 * b;
+                let [l, m, s] = [l, m, s].map(|c| c * c * c);
+                let rgb = Rgb::from_linear(LinSrgb::new(
+                    4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+                    -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+                    -0.0041960863 * l - 0.7034186147 * m + 1.7076109934 * s,
+                ));
+                [rgb.red, rgb.green, rgb.blue]
+            }
+            LightnessSpace::Cielab => {
+                let rgb: Rgb = Lab::new(lightness, a, b).into();
+                [rgb.red, rgb.green, rgb.blue]
+            }
+        }
+    }
+
+    /// The lightness of an sRGB color.
+    #[allow(clippy::excessive_precision)]
+    fn lightness(&self, rgb: Rgb) -> f32 {
+        match self {
+            LightnessSpace::Oklab => {
+                let LinSrgb {
+                    red: r,
+                    green: g,
+                    blue: b,
+                    ..
+                } = rgb.into_linear();
+                let l = 0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b;
+                let m = 0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b;
+                let s = 0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b;
+                let [l, m, s] = [l, m, s].map(f32::cbrt);
+                0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s
+            }
+            LightnessSpace::Cielab => {
+                let lab: Lab = rgb.into();
+                lab.l
+            }
+        }
+    }
+}
+
+/// How [`LightnessColorStrategy`] picks the chroma of a color, in the units of
+/// its [`LightnessSpace`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Chroma {
+    /// The same chroma for every sample.
+    Fixed(f32),
+    /// A chroma proportional to the magnitude of the sample, from grey at
+    /// silence up to the given chroma at full scale.
+    FromSample(f32),
+}
+
+/// A [`ColorStrategy`] which maps samples to the perceptual lightness of a
+/// color, from black at the most negative sample to white at the most
+/// positive.
+///
+/// Brightness and contrast adjustments in an image editor then act like an
+/// offset and gain on the audio. Only the lightness is decoded, and colors
+/// whose chroma is out of the sRGB gamut are desaturated rather than clipped
+/// when encoding, so that their lightness is kept.
+pub struct LightnessColorStrategy {
+    space: LightnessSpace,
+    chroma: Chroma,
+    hue: f32,
+}
+
+impl LightnessColorStrategy {
+    /// Create a strategy whose colors have the given hue, in degrees.
+    pub fn new(space: LightnessSpace, chroma: Chroma, hue: f32) -> Self {
+        LightnessColorStrategy {
+            space,
+            chroma,
+            hue: hue.to_radians(),
+        }
+    }
+
+    /// Whether an unclamped sRGB color lies within the gamut.
+    fn in_gamut(rgb: [f32; 3]) -> bool {
+        rgb.iter().all(|&c| (-1e-4..=1. + 1e-4).contains(&c))
+    }
+}
+
+impl ColorStrategy for LightnessColorStrategy {
+    fn sample_to_color(&self, sample: i16) -> image::Rgba<u8> {
+        let t = (sample as f32 + 2u32.pow(15) as f32) / (u16::MAX as f32);
+        let lightness = t * self.space.white();
+        let chroma = match self.chroma {
+            Chroma::Fixed(chroma) => chroma,
+            Chroma::FromSample(chroma) => chroma * (2. * t - 1.).abs(),
+        };
+
+        let (sin, cos) = self.hue.sin_cos();
+        let color = |chroma: f32| {
+            self.space.to_rgb(lightness, chroma * cos, chroma * sin)
+        };
+
+        // reduce the chroma until the color fits in the gamut
+        let mut rgb = color(chroma);
+        if !Self::in_gamut(rgb) {
+            let (mut low, mut high) = (0., chroma);
+            rgb = color(low);
+            for _ in 0..16 {
+                let middle = (low + high) / 2.;
+                let candidate = color(middle);
+                if Self::in_gamut(candidate) {
+                    low = middle;
+                    rgb = candidate;
+                } else {
+                    high = middle;
+                }
+            }
+        }
+
+        let [r, g, b] = rgb.map(|c| (255. * c.clamp(0., 1.)).round() as u8);
+        image::Rgba([r, g, b, 255])
+    }
+
+    fn color_to_sample(&self, color: &image::Rgba<u8>) -> i16 {
+        let [r, g, b, _] = color.0;
+        let rgb = Rgb::new(r as f32 / 255., g as f32 / 255., b as f32 / 255.);
+        let t = (self.space.lightness(rgb) / self.space.white()).clamp(0., 1.);
+        let sample = t * (u16::MAX as f32) - 2u32.pow(15) as f32;
+        sample.round() as i16
+    }
+}
+
+/// A [`ColorStrategy`] which packs several samples into the channels of each
+/// color, including the alpha channel: either two samples at 16 bits, in the
+/// red and green and the blue and alpha channels, or four samples at 8 bits,
+/// one per channel.
+///
+/// Packed colors hold much more audio per pixel, but look like noise and do
+/// not survive image effects. Samples packed at 8 bits are quantized linearly,
+/// so wrap this strategy in a [`CompandedColorStrategy`] to keep the detail of
+/// quiet audio.
+pub struct PackedColorStrategy {
+    samples: usize,
+}
+
+impl PackedColorStrategy {
+    pub fn new(samples: usize) -> Self {
+        assert!(samples == 2 || samples == 4, "can only pack 2 or 4 samples");
+
+        PackedColorStrategy { samples }
+    }
+}
+
+impl ColorStrategy for PackedColorStrategy {
+    fn sample_to_color(&self, sample: i16) -> image::Rgba<u8> {
+        self.samples_to_color(&[sample; 4][..self.samples])
+    }
+
+    fn color_to_sample(&self, color: &image::Rgba<u8>) -> i16 {
+        let mut samples = [0; 4];
+        self.color_to_samples(color, &mut samples[..self.samples]);
+        samples[0]
+    }
+
+    fn samples_per_pixel(&self) -> usize {
+        self.samples
+    }
+
+    fn uses_alpha(&self) -> bool {
+        true
+    }
+
+    fn samples_to_color(&self, samples: &[i16]) -> image::Rgba<u8> {
+        let unsigned = |sample: i16| (sample as i32 + 2i32.pow(15)) as u16;
+        let mut color = [0; 4];
+        if self.samples == 2 {
+            color[..2].copy_from_slice(&unsigned(samples[0]).to_be_bytes());
+            color[2..].copy_from_slice(&unsigned(samples[1]).to_be_bytes());
+        } else {
+            for (channel, &sample) in color.iter_mut().zip(samples) {
+                *channel = (unsigned(sample) >> 8) as u8;
+            }
+        }
+        image::Rgba(color)
+    }
+
+    fn color_to_samples(&self, color: &image::Rgba<u8>, samples: &mut [i16]) {
+        let [r, g, b, a] = color.0;
+        let signed = |unsigned: u16| (unsigned as i32 - 2i32.pow(15)) as i16;
+        if self.samples == 2 {
+            samples[0] = signed(u16::from_be_bytes([r, g]));
+            samples[1] = signed(u16::from_be_bytes([b, a]));
+        } else {
+            for (sample, channel) in samples.iter_mut().zip(color.0) {
+                // center the sample within the range of values that were dropped
+                *sample = signed((channel as u16) << 8 | 0x80);
+            }
+        }
+    }
+}
+
+/// A logarithmic companding law, as used by telephony codecs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Companding {
+    /// The μ-law, with μ = 255.
+    MuLaw,
+    /// The A-law, with A = 87.6.
+    ALaw,
+}
+
+impl Companding {
+    const MU: f32 = 255.;
+    const A: f32 = 87.6;
+
+    /// Compress a sample between -1 and 1.
+    pub fn compress(&self, x: f32) -> f32 {
+        let magnitude = x.abs().min(1.);
+        let y = match self {
+            Companding::MuLaw => {
+                (Self::MU * magnitude).ln_1p() / Self::MU.ln_1p()
+            }
+            Companding::ALaw => {
+                let denominator = 1. + Self::A.ln();
+                if magnitude < 1. / Self::A {
+                    Self::A * magnitude / denominator
+                } else {
+                    (1. + (Self::A * magnitude).ln()) / denominator
+                }
+            }
+        };
+        y.copysign(x)
+    }
+
+    /// The inverse of [`Companding::compress`].
+    pub fn expand(&self, y: f32) -> f32 {
+        let magnitude = y.abs().min(1.);
+        let x = match self {
+            Companding::MuLaw => {
+                (Self::MU.ln_1p() * magnitude).exp_m1() / Self::MU
+            }
+            Companding::ALaw => {
+                let denominator = 1. + Self::A.ln();
+                if magnitude < 1. / denominator {
+                    magnitude * denominator / Self::A
+                } else {
+                    (magnitude * denominator - 1.).exp() / Self::A
+                }
+            }
+        };
+        x.copysign(y)
+    }
+
+    fn compress_sample(&self, sample: i16) -> i16 {
+        (self.compress(sample.convert_to_f32()) * 32768.).round() as i16
+    }
+
+    fn expand_sample(&self, sample: i16) -> i16 {
+        (self.expand(sample.convert_to_f32()) * 32768.).round() as i16
+    }
+}
+
+/// A [`ColorStrategy`] which compands samples before passing them to an inner
+/// strategy, and expands them again when decoding.
+///
+/// Quiet samples are spread over more of the colors of the inner strategy, so
+/// strategies which quantize samples to 8 bits keep the detail of quiet audio
+/// at the cost of loud audio.
+pub struct CompandedColorStrategy<C>
+where
+    C: ColorStrategy,
+{
+    inner: C,
+    companding: Companding,
+    /// The compressed window of the last sample converted in context, kept to
+    /// reuse its allocation.
+    window: RefCell<Vec<f32>>,
+}
+
+impl<C> CompandedColorStrategy<C>
+where
+    C: ColorStrategy,
+{
+    pub fn new(inner: C, companding: Companding) -> Self {
+        CompandedColorStrategy {
+            inner,
+            companding,
+            window: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl<C> ColorStrategy for CompandedColorStrategy<C>
+where
+    C: ColorStrategy,
+{
+    fn sample_to_color(&self, sample: i16) -> image::Rgba<u8> {
+        self.inner
+            .sample_to_color(self.companding.compress_sample(sample))
+    }
+
+    fn color_to_sample(&self, color: &image::Rgba<u8>) -> i16 {
+        self.companding
+            .expand_sample(self.inner.color_to_sample(color))
+    }
+
+    fn embed_sample(
+        &self,
+        sample: i16,
+        cover: &image::Rgba<u8>,
+    ) -> image::Rgba<u8> {
+        self.inner
+            .embed_sample(self.companding.compress_sample(sample), cover)
+    }
+
+    fn sample_to_color_f32(&self, sample: f32) -> image::Rgba<u8> {
+        self.inner
+            .sample_to_color_f32(self.companding.compress(sample))
+    }
+
+    fn color_to_sample_f32(&self, color: &image::Rgba<u8>) -> f32 {
+        self.companding
+            .expand(self.inner.color_to_sample_f32(color))
+    }
+
+    fn samples_per_pixel(&self) -> usize {
+        self.inner.samples_per_pixel()
+    }
+
+    fn uses_alpha(&self) -> bool {
+        self.inner.uses_alpha()
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        self.inner.pixel_format()
+    }
+
+    fn samples_to_color(&self, samples: &[i16]) -> image::Rgba<u8> {
+        // no strategy packs more samples than a color has channels
+        let mut compressed = [0; 4];
+        for (compressed, &sample) in compressed.iter_mut().zip(samples) {
+            *compressed = self.companding.compress_sample(sample);
+        }
+        self.inner.samples_to_color(&compressed[..samples.len()])
+    }
+
+    fn color_to_samples(&self, color: &image::Rgba<u8>, samples: &mut [i16]) {
+        self.inner.color_to_samples(color, samples);
+        for sample in samples {
+            *sample = self.companding.expand_sample(*sample);
+        }
+    }
+
+    fn context(&self) -> usize {
+        self.inner.context()
+    }
+
+    fn sample_to_color_in_context(
+        &self,
+        window: &[f32],
+        center: usize,
+    ) -> image::Rgba<u8> {
+        let mut compressed = self.window.borrow_mut();
+        compressed.clear();
+        compressed.extend(
+            window
+                .iter()
+                .map(|&sample| self.companding.compress(sample)),
+        );
+        self.inner.sample_to_color_in_context(&compressed, center)
+    }
+
+    fn color_to_sample_in_context(
+        &self,
+        color: &image::Rgba<u8>,
+        state: &mut f32,
+    ) -> f32 {
+        self.companding
+            .expand(self.inner.color_to_sample_in_context(color, state))
+    }
+}
+
+/// How [`CachedColorStrategy`] caches the colors it has converted to samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InverseTable {
+    /// Remember each color the first time it is converted.
+    Memoized,
+    /// Convert every opaque color the first time any color is converted, which
+    /// takes a while and 32 MiB of memory, but makes every lookup after it
+    /// cheap.
+    Precomputed,
+}
+
+/// A [`ColorStrategy`] which caches the conversions of an inner strategy in
+/// lookup tables, for strategies which are expensive to compute.
+///
+/// Every sample is converted to a color up front. Strategies which pack
+/// several samples into a color or look at the samples around each one are not
+/// cached. The precomputed inverse table only covers opaque colors, so other
+/// colors are still converted by the inner strategy, and strategies which use
+/// the alpha channel are memoized instead.
+///
+/// Conversions always go through 16-bit samples, so any extra precision that
+/// the inner strategy has between them, such as that of a
+/// [`CompandedColorStrategy`], is lost.
+pub struct CachedColorStrategy<C>
+where
+    C: ColorStrategy,
+{
+    inner: C,
+    colors: Vec<image::Rgba<u8>>,
+    samples: Option<RefCell<Vec<i16>>>,
+    memo: Option<RefCell<HashMap<[u8; 4], i16>>>,
+}
+
+impl<C> CachedColorStrategy<C>
+where
+    C: ColorStrategy,
+{
+    pub fn new(inner: C, inverse: InverseTable) -> Self {
+        let cached = inner.samples_per_pixel() == 1 && inner.context() == 0;
+        let colors = if cached {
+            (i16::MIN..=i16::MAX)
+                .map(|sample| inner.sample_to_color(sample))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        // the table is filled in on first use, since encoding never needs it
+        let precompute = cached
+            && inverse == InverseTable::Precomputed
+            && !inner.uses_alpha();
+        let samples = precompute.then(|| RefCell::new(Vec::new()));
+        let memo =
+            (cached && !precompute).then(|| RefCell::new(HashMap::new()));
+
+        CachedColorStrategy {
+            inner,
+            colors,
+            samples,
+            memo,
+        }
+    }
+}
+
+impl<C> ColorStrategy for CachedColorStrategy<C>
+where
+    C: ColorStrategy,
+{
+    fn sample_to_color(&self, sample: i16) -> image::Rgba<u8> {
+        match self.colors.get((sample as i32 - i16::MIN as i32) as usize) {
+            Some(color) => *color,
+            None => self.inner.sample_to_color(sample),
+        }
+    }
+
+    fn color_to_sample(&self, color: &image::Rgba<u8>) -> i16 {
+        let [r, g, b, a] = color.0;
+        if let (255, Some(samples)) = (a, &self.samples) {
+            let mut samples = samples.borrow_mut();
+            if samples.is_empty() {
+                *samples = (0..1 << 24)
+                    .map(|rgb: u32| {
+                        let [_, r, g, b] = rgb.to_be_bytes();
+                        self.inner.color_to_sample(&image::Rgba([r, g, b, 255]))
+                    })
+                    .collect();
+            }
+            return samples[u32::from_be_bytes([0, r, g, b]) as usize];
+        }
+
+        match &self.memo {
+            Some(memo) => *memo
+                .borrow_mut()
+                .entry(color.0)
+                .or_insert_with(|| self.inner.color_to_sample(color)),
+            None => self.inner.color_to_sample(color),
+        }
+    }
+
+    fn embed_sample(
+        &self,
+        sample: i16,
+        cover: &image::Rgba<u8>,
+    ) -> image::Rgba<u8> {
+        self.inner.embed_sample(sample, cover)
+    }
+
+    fn samples_per_pixel(&self) -> usize {
+        self.inner.samples_per_pixel()
+    }
+
+    fn uses_alpha(&self) -> bool {
+        self.inner.uses_alpha()
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        self.inner.pixel_format()
+    }
+
+    fn samples_to_color(&self, samples: &[i16]) -> image::Rgba<u8> {
+        if self.colors.is_empty() {
+            self.inner.samples_to_color(samples)
+        } else {
+            self.sample_to_color(samples[0])
+        }
+    }
+
+    fn color_to_samples(&self, color: &image::Rgba<u8>, samples: &mut [i16]) {
+        if self.colors.is_empty() {
+            self.inner.color_to_samples(color, samples)
+        } else {
+            samples[0] = self.color_to_sample(color);
+        }
+    }
+
+    fn context(&self) -> usize {
+        self.inner.context()
+    }
+
+    fn sample_to_color_in_context(
+        &self,
+        window: &[f32],
+        center: usize,
+    ) -> image::Rgba<u8> {
+        self.inner.sample_to_color_in_context(window, center)
+    }
+
+    fn color_to_sample_in_context(
+        &self,
+        color: &image::Rgba<u8>,
+        state: &mut f32,
+    ) -> f32 {
+        self.inner.color_to_sample_in_context(color, state)
+    }
+}
+
+/// A [`ColorStrategy`] which hides samples in the least significant bits of
+/// the red, green and blue channels of a cover color.
+///
+/// Each channel carries `bits` bits. If there are fewer than 16 bits in total,
+/// only the most significant bits of each sample are kept.
+pub struct LsbColorStrategy {
+    bits: u32,
+}
+
+impl LsbColorStrategy {
+    /// The color used as the cover by [`ColorStrategy::sample_to_color`].
+    const DEFAULT_COVER: image::Rgba<u8> = image::Rgba([128, 128, 128, 255]);
+
+    pub fn new(bits: u32) -> Self {
+        assert!((1..=8).contains(&bits));
+
+        LsbColorStrategy { bits }
+    }
+
+    /// The number of bits which can be hidden in a single color.
+    pub fn payload_bits(&self) -> u32 {
+        3 * self.bits
+    }
+
+    /// Hide the lowest [`LsbColorStrategy::payload_bits`] bits of `value`
+    /// inside of `cover`.
+    pub fn embed_bits(
+        &self,
+        value: u32,
+        cover: &image::Rgba<u8>,
+    ) -> image::Rgba<u8> {
+        let mask = (1u32 << self.bits) - 1;
+        let [r, g, b, a] = cover.0;
+        let embed = |channel: u8, shift: u32| {
+            ((channel as u32 & !mask) | ((value >> shift) & mask)) as u8
+        };
+        image::Rgba([
+            embed(r, 2 * self.bits),
+            embed(g, self.bits),
+            embed(b, 0),
+            a,
+        ])
+    }
+
+    /// Extract the bits hidden by [`LsbColorStrategy::embed_bits`].
+    pub fn extract_bits(&self, color: &image::Rgba<u8>) -> u32 {
+        let mask = (1u32 << self.bits) - 1;
+        let [r, g, b, _] = color.0;
+        ((r as u32 & mask) << (2 * self.bits))
+            | ((g as u32 & mask) << self.bits)
+            | (b as u32 & mask)
+    }
+}
+
+impl ColorStrategy for LsbColorStrategy {
+    fn sample_to_color(&self, sample: i16) -> image::Rgba<u8> {
+        self.embed_sample(sample, &Self::DEFAULT_COVER)
+    }
+
+    fn color_to_sample(&self, color: &image::Rgba<u8>) -> i16 {
+        let value = self.extract_bits(color);
+        let payload = self.payload_bits();
+        let unsigned = if payload >= 16 {
+            value >> (payload - 16)
+        } else {
+            // center the sample within the range of values that were dropped
+            (value << (16 - payload)) | (1 << (15 - payload))
+        };
+        (unsigned as i32 - 2i32.pow(15)) as i16
+    }
+
+    fn sample_to_color_f32(&self, sample: f32) -> image::Rgba<u8> {
+        let max = (1u32 << self.payload_bits()) - 1;
+        let value = ((sample + 1.) / 2. * (max as f32 + 1.)).max(0.) as u32;
+        self.embed_bits(value.min(max), &Self::DEFAULT_COVER)
+    }
+
+    fn color_to_sample_f32(&self, color: &image::Rgba<u8>) -> f32 {
+        let steps = (1u32 << self.payload_bits()) as f32;
+        // center the sample within the range of values it stands for
+        (self.extract_bits(color) as f32 + 0.5) / steps * 2. - 1.
+    }
+
+    fn embed_sample(
+        &self,
+        sample: i16,
+        cover: &image::Rgba<u8>,
+    ) -> image::Rgba<u8> {
+        let unsigned = (sample as i32 + 2i32.pow(15)) as u32;
+        let payload = self.payload_bits();
+        let value = if payload >= 16 {
+            unsigned << (payload - 16)
+        } else {
+            unsigned >> (16 - payload)
+        };
+        self.embed_bits(value, cover)
+    }
+}
+
+/// A [`ColorStrategy`] which maps the analytic signal of the audio to colors,
+/// so that the image reads like a spectrogram: its instantaneous amplitude to
+/// brightness, and its instantaneous frequency to hue, from red at silence to
+/// magenta at the Nyquist frequency.
+///
+/// The analytic signal comes from a windowed Hilbert transform over `taps`
+/// samples on either side of each sample. Decoding adds up the frequencies
+/// of each channel into a phase, so colors must be decoded in order, as a
+/// [`Decoder`](crate::Decoder) does; a single color decodes to its amplitude.
+/// The audio is only approximately restored, with a different phase, and
+/// frequencies are lost in quiet parts where the colors are too dark to hold a
+/// hue.
+pub struct AnalyticColorStrategy {
+    kernel: Vec<f32>,
+}
+
+impl AnalyticColorStrategy {
+    /// The hue of the Nyquist frequency, in turns.
+    const NYQUIST_HUE: f32 = 5. / 6.;
+
+    pub fn new(taps: usize) -> Self {
+        assert!(taps > 0, "Hilbert transform must have at least one tap");
+
+        // ideal Hilbert transformer, tapered by a Hann window
+        let kernel = (1..=taps)
+            .map(|m| {
+                if m % 2 == 0 {
+                    return 0.;
+                }
+                let m = m as f32;
+                let window = 0.5 + 0.5 * (PI * m / (taps + 1) as f32).cos();
+                2. / (PI * m) * window
+            })
+            .collect();
+
+        AnalyticColorStrategy { kernel }
+    }
+
+    /// The Hilbert transform of the sample at `index` of `window`, treating
+    /// samples outside of it as silence.
+    fn hilbert(&self, window: &[f32], index: usize) -> f32 {
+        let sample = |offset: isize| {
+            window
+                .get((index as isize + offset) as usize)
+                .copied()
+                .unwrap_or(0.)
+        };
+
+        self.kernel
+            .iter()
+            .zip(1..)
+            .map(|(h, m)| h * (sample(-m) - sample(m)))
+            .sum()
+    }
+
+    /// The analytic signal at `index` of `window`, as a real and imaginary
+    /// part.
+    fn analytic(&self, window: &[f32], index: usize) -> (f32, f32) {
+        (window[index], self.hilbert(window, index))
+    }
+}
+
+impl ColorStrategy for AnalyticColorStrategy {
+    fn sample_to_color(&self, sample: i16) -> image::Rgba<u8> {
+        self.sample_to_color_f32(sample.convert_to_f32())
+    }
+
     fn color_to_sample(&self, color: &image::Rgba<u8>) -> i16 {
+        self.color_to_sample_f32(color).convert_to_i16()
+    }
+
+    fn sample_to_color_f32(&self, sample: f32) -> image::Rgba<u8> {
+        self.sample_to_color_in_context(&[sample], 0)
+    }
+
+    fn color_to_sample_f32(&self, color: &image::Rgba<u8>) -> f32 {
+        self.color_to_sample_in_context(color, &mut 0.)
+    }
+
+    fn context(&self) -> usize {
+        // the previous sample is also needed for the instantaneous frequency
+        self.kernel.len() + 1
+    }
+
+    fn sample_to_color_in_context(
+        &self,
+        window: &[f32],
+        center: usize,
+    ) -> image::Rgba<u8> {
+        let (re, im) = self.analytic(window, center);
+        let amplitude = re.hypot(im).min(1.);
+
+        // the phase difference between neighboring samples, or towards the
+        // next sample at the very start
+        let neighbors = if center > 0 {
+            Some((self.analytic(window, center - 1), (re, im)))
+        } else if center + 1 < window.len() {
+            Some(((re, im), self.analytic(window, center + 1)))
+        } else {
+            None
+        };
+        let frequency = neighbors.map_or(0., |((re0, im0), (re1, im1))| {
+            (im1 * re0 - re1 * im0).atan2(re1 * re0 + im1 * im0).abs()
+        });
+
+        let hue = frequency / PI * Self::NYQUIST_HUE;
+        let rgb: Rgb = hsv(hue, 1., amplitude).into();
+        image::Rgba([
+            (255. * rgb.red).round() as u8,
+            (255. * rgb.green).round() as u8,
+            (255. * rgb.blue).round() as u8,
+            255,
+        ])
+    }
+
+    fn color_to_sample_in_context(
+        &self,
+        color: &image::Rgba<u8>,
+        phase: &mut f32,
+    ) -> f32 {
         let [r, g, b, _] = color.0;
         let hsv: Hsv =
             Rgb::new(r as f32 / 255., g as f32 / 255., b as f32 / 255.).into();
-        let sample = (hsv.hue.to_positive_radians() / std::f32::consts::TAU)
-            * (2u32.pow(16) as f32)
-            - (2u32.pow(15) as f32);
-        sample as i16
+
+        // hues past the Nyquist frequency wrap around to the nearest end
+        let hue = hsv.hue.to_positive_degrees() / 360.;
+        let hue = if hue <= (1. + Self::NYQUIST_HUE) / 2. {
+            hue.min(Self::NYQUIST_HUE)
+        } else {
+            0.
+        };
+        let frequency = hue / Self::NYQUIST_HUE * PI;
+
+        *phase = (*phase + frequency) % (2. * PI);
+        hsv.value * phase.cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use nannou::image::RgbaImage;
+
+    use super::*;
+    use crate::{
+        encode_canvas, strategy::space::HilbertSpaceStrategy, AlphaSource,
+        Decoder, Dither, FillPolicy,
+    };
+
+    /// The largest error of converting every sample to a color and back.
+    fn max_error(strategy: &impl ColorStrategy) -> i32 {
+        (i16::MIN..=i16::MAX)
+            .map(|sample| {
+                let color = strategy.sample_to_color(sample);
+                (strategy.color_to_sample(&color) as i32 - sample as i32).abs()
+            })
+            .max()
+            .unwrap()
+    }
+
+    /// Whether larger samples never decode to smaller ones.
+    fn is_monotonic(strategy: &impl ColorStrategy) -> bool {
+        let samples = (i16::MIN..=i16::MAX).step_by(64).map(|sample| {
+            strategy.color_to_sample(&strategy.sample_to_color(sample))
+        });
+        samples
+            .collect::<Vec<_>>()
+            .windows(2)
+            .all(|pair| pair[0] <= pair[1])
+    }
+
+    #[test]
+    fn grey_keeps_its_bits() {
+        let grey16 = GreyColorStrategy::new(16);
+        let grey8 = GreyColorStrategy::new(8);
+        assert_eq!(max_error(&grey16), 0);
+        assert!(max_error(&grey8) <= 128);
+        assert_eq!(grey16.pixel_format(), PixelFormat::LumaA16);
+        assert_eq!(grey8.pixel_format(), PixelFormat::Rgba8);
+
+        for sample in (i16::MIN..=i16::MAX).step_by(97) {
+            let color = grey16.sample_to_color(sample);
+            let luma = GreyColorStrategy::to_luma16(&color);
+            assert_eq!(GreyColorStrategy::from_luma16(&luma), color);
+
+            // 8-bit greys read as 16 bits are scaled up
+            let color = grey8.sample_to_color(sample);
+            let error = grey16.color_to_sample(&color) as i32 - sample as i32;
+            assert!(error.abs() <= 128);
+        }
+    }
+
+    #[test]
+    fn colormaps_run_from_the_first_stop_to_the_last() {
+        for colormap in [Colormap::Viridis, Colormap::Magma] {
+            let strategy = ColormapColorStrategy::from_colormap(colormap);
+            let stops = colormap.stops();
+            let rgb = |sample| {
+                let [r, g, b, _] = strategy.sample_to_color(sample).0;
+                [r, g, b]
+            };
+            assert_eq!(rgb(i16::MIN), stops[0]);
+            assert_eq!(rgb(i16::MAX), stops[stops.len() - 1]);
+
+            assert!(max_error(&strategy) < 256, "{:?}", colormap);
+            assert!(is_monotonic(&strategy), "{:?}", colormap);
+        }
+
+        let strategy = ColormapColorStrategy::new(&[[0, 0, 0], [255, 0, 255]]);
+        assert!(max_error(&strategy) <= 129);
+    }
+
+    #[test]
+    fn lightness_runs_from_black_to_white() {
+        for space in [LightnessSpace::Oklab, LightnessSpace::Cielab] {
+            for chroma in [Chroma::Fixed(0.), Chroma::FromSample(0.1)] {
+                let strategy = LightnessColorStrategy::new(space, chroma, 30.);
+                assert_eq!(
+                    strategy.sample_to_color(i16::MIN).0,
+                    [0, 0, 0, 255]
+                );
+                assert_eq!(
+                    strategy.sample_to_color(i16::MAX).0,
+                    [255, 255, 255, 255]
+                );
+                assert!(is_monotonic(&strategy), "{:?} {:?}", space, chroma);
+            }
+        }
+    }
+
+    #[test]
+    fn lightness_survives_out_of_gamut_chroma() {
+        // far more chroma than sRGB holds, which is given up to keep the
+        // lightness, away from the ends where 8 bits are too coarse anyway
+        let strategy = LightnessColorStrategy::new(
+            LightnessSpace::Oklab,
+            Chroma::Fixed(1.),
+            140.,
+        );
+        for sample in (-16384..16384).step_by(7) {
+            let color = strategy.sample_to_color(sample);
+            let error = strategy.color_to_sample(&color) - sample;
+            assert!(error.abs() < 512, "{} decodes {} off", sample, error);
+        }
+    }
+
+    #[test]
+    fn packed_samples_round_trip() {
+        let samples = [-32768, -1, 0, 12345];
+        let mut decoded = [0; 4];
+
+        let strategy = PackedColorStrategy::new(2);
+        let color = strategy.samples_to_color(&samples[..2]);
+        strategy.color_to_samples(&color, &mut decoded[..2]);
+        assert_eq!(decoded[..2], samples[..2]);
+        assert_eq!(strategy.color_to_sample(&strategy.sample_to_color(-1)), -1);
+
+        let strategy = PackedColorStrategy::new(4);
+        let color = strategy.samples_to_color(&samples);
+        strategy.color_to_samples(&color, &mut decoded);
+        for (decoded, sample) in decoded.iter().zip(samples) {
+            assert!((*decoded as i32 - sample as i32).abs() <= 128);
+        }
+    }
+
+    #[test]
+    fn companding_inverts() {
+        for companding in [Companding::MuLaw, Companding::ALaw] {
+            for i in -1000..=1000 {
+                let x = i as f32 / 1000.;
+                let y = companding.compress(x);
+                assert!(y.abs() <= 1. && y.signum() == x.signum());
+                assert!((companding.expand(y) - x).abs() < 1e-5, "{}", x);
+            }
+            assert_eq!(companding.compress(1.), 1.);
+            assert_eq!(companding.compress(0.), 0.);
+        }
+    }
+
+    #[test]
+    fn companding_keeps_quiet_detail() {
+        let grey = || GreyColorStrategy::new(8);
+        let companded = CompandedColorStrategy::new(grey(), Companding::MuLaw);
+        let error = |strategy: &dyn ColorStrategy, sample| {
+            let color = strategy.sample_to_color(sample);
+            (strategy.color_to_sample(&color) as i32 - sample as i32).abs()
+        };
+
+        for sample in [-300, -40, 7, 150] {
+            assert!(error(&companded, sample) < error(&grey(), sample));
+        }
+        assert!(error(&companded, 30000) < 1024);
+    }
+
+    #[test]
+    fn companding_keeps_quiet_detail_of_packed_samples() {
+        let packed = || PackedColorStrategy::new(4);
+        let companded =
+            CompandedColorStrategy::new(packed(), Companding::MuLaw);
+        let error = |strategy: &dyn ColorStrategy| {
+            let samples = [-300, -40, 7, 150];
+            let color = strategy.samples_to_color(&samples);
+            let mut decoded = [0; 4];
+            strategy.color_to_samples(&color, &mut decoded);
+            (samples.iter().zip(decoded))
+                .map(|(&sample, decoded)| {
+                    (sample as i32 - decoded as i32).abs()
+                })
+                .max()
+                .unwrap()
+        };
+
+        assert!(error(&companded) < error(&packed()));
+    }
+
+    #[test]
+    fn caching_matches_the_inner_strategy() {
+        let hue = || HueColorStrategy::new(1., 1.);
+        let cached = CachedColorStrategy::new(hue(), InverseTable::Memoized);
+        for sample in (i16::MIN..=i16::MAX).step_by(61) {
+            let color = hue().sample_to_color(sample);
+            assert_eq!(cached.sample_to_color(sample), color);
+            assert_eq!(
+                cached.color_to_sample(&color),
+                hue().color_to_sample(&color)
+            );
+            // the second lookup comes from the memo
+            assert_eq!(
+                cached.color_to_sample(&color),
+                hue().color_to_sample(&color)
+            );
+        }
+
+        let grey = || GreyColorStrategy::new(8);
+        let cached =
+            CachedColorStrategy::new(grey(), InverseTable::Precomputed);
+        // the inverse table is only built by the first lookup
+        assert!(cached.samples.as_ref().unwrap().borrow().is_empty());
+        for color in [[0, 0, 0, 255], [12, 200, 99, 255], [255, 255, 255, 0]] {
+            let color = image::Rgba(color);
+            assert_eq!(
+                cached.color_to_sample(&color),
+                grey().color_to_sample(&color)
+            );
+        }
+    }
+
+    #[test]
+    fn caching_skips_packed_strategies() {
+        let packed = PackedColorStrategy::new(2);
+        let cached = CachedColorStrategy::new(
+            PackedColorStrategy::new(2),
+            InverseTable::Precomputed,
+        );
+        let color = image::Rgba([1, 2, 3, 4]);
+        let (mut expected, mut samples) = ([0; 2], [0; 2]);
+        packed.color_to_samples(&color, &mut expected);
+        cached.color_to_samples(&color, &mut samples);
+        assert_eq!(samples, expected);
+        assert_eq!(cached.color_to_sample(&color), expected[0]);
+    }
+
+    #[test]
+    fn analytic_tones_keep_their_amplitude_and_frequency() {
+        // a different tone in each channel, so that mixing them up shows
+        let frames = 2048;
+        let tones = [(0.5, 32.), (0.25, 20.)];
+        let samples = (0..frames)
+            .flat_map(|i| {
+                tones.map(|(amplitude, period)| {
+                    let phase = TAU * i as f32 / period;
+                    (amplitude * phase.sin() * 32767.) as i16
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let image: RgbaImage = encode_canvas(
+            samples.iter().copied(),
+            2,
+            FillPolicy::Transparent,
+            AlphaSource::Opaque,
+            Dither::default(),
+            Box::new(AnalyticColorStrategy::new(32)),
+            Box::new(HilbertSpaceStrategy::from_size(64)),
+        );
+        let decoder = Decoder::new(
+            image,
+            Box::new(AnalyticColorStrategy::new(32)),
+            Box::new(HilbertSpaceStrategy::from_size(64)),
+        )
+        .with_channels(2)
+        .with_length(samples.len());
+        let output = decoder.collect::<Vec<_>>();
+        assert_eq!(output.len(), samples.len());
+
+        for (channel, (amplitude, period)) in tones.into_iter().enumerate() {
+            // leave out the edges, where the transform runs out of context
+            let decoded = output
+                .iter()
+                .skip(channel)
+                .step_by(2)
+                .skip(64)
+                .take(frames - 128)
+                .map(|&sample| sample.convert_to_f32())
+                .collect::<Vec<_>>();
+
+            let rms =
+                (decoded.iter().map(|sample| sample * sample).sum::<f32>()
+                    / decoded.len() as f32)
+                    .sqrt();
+            let expected = amplitude / 2f32.sqrt();
+            assert!(
+                (rms / expected - 1.).abs() < 0.1,
+                "channel {} has amplitude {} instead of {}",
+                channel,
+                rms,
+                expected
+            );
+
+            let crossings = decoded
+                .windows(2)
+                .filter(|pair| (pair[0] < 0.) != (pair[1] < 0.))
+                .count() as f32;
+            let expected = 2. * decoded.len() as f32 / period;
+            assert!(
+                (crossings / expected - 1.).abs() < 0.05,
+                "channel {} crosses zero {} times instead of {}",
+                channel,
+                crossings,
+                expected
+            );
+        }
     }
 }
@@ -0,0 +1,99 @@
+//! Mapping strategies between colors and complex values.
+
+use dsp::num_complex::Complex32;
+use nannou::{
+    color::{hsv, Hsv, Rgb},
+    image,
+};
+
+/// A color strategy which represents a mapping between complex values, such
+/// as the bins of a spectrum, and colors.
+///
+/// Like a [`ColorStrategy`](super::ColorStrategy), these functions should
+/// accept any value without panicking, and each should be as close to the
+/// inverse of the other as colors allow.
+pub trait ComplexColorStrategy {
+    /// Convert a complex value to a color.
+    fn value_to_color(&self, value: Complex32) -> image::Rgba<u8>;
+
+    /// Convert a color to a complex value.
+    fn color_to_value(&self, color: &image::Rgba<u8>) -> Complex32;
+}
+
+/// A [`ComplexColorStrategy`] which colors the complex plane by domain
+/// coloring: the phase of a value maps to hue, and its magnitude to
+/// brightness.
+///
+/// Magnitudes from zero up to infinity map to brightnesses from black up to
+/// full brightness, reaching half brightness at a magnitude of `scale`. The
+/// phase of dark colors is less precise, and the phase of black is lost.
+pub struct DomainColorStrategy {
+    scale: f32,
+}
+
+impl DomainColorStrategy {
+    pub fn new(scale: f32) -> Self {
+        assert!(scale > 0.);
+
+        DomainColorStrategy { scale }
+    }
+}
+
+impl ComplexColorStrategy for DomainColorStrategy {
+    fn value_to_color(&self, value: Complex32) -> image::Rgba<u8> {
+        let (magnitude, phase) = value.to_polar();
+        let magnitude = if magnitude.is_finite() { magnitude } else { 0. };
+        let hue =
+            phase.rem_euclid(std::f32::consts::TAU) / std::f32::consts::TAU;
+        let brightness = magnitude / (magnitude + self.scale);
+
+        let rgb: Rgb = hsv(hue, 1., brightness).into();
+        image::Rgba([
+            (255. * rgb.red).round() as u8,
+            (255. * rgb.green).round() as u8,
+            (255. * rgb.blue).round() as u8,
+            255,
+        ])
+    }
+
+    fn color_to_value(&self, color: &image::Rgba<u8>) -> Complex32 {
+        let [r, g, b, _] = color.0;
+        let hsv: Hsv =
+            Rgb::new(r as f32 / 255., g as f32 / 255., b as f32 / 255.).into();
+
+        // full brightness is infinitely far away, so stop half a step short
+        let brightness = hsv.value.min(1. - 0.5 / 255.);
+        let magnitude = self.scale * brightness / (1. - brightness);
+        Complex32::from_polar(magnitude, hsv.hue.to_positive_radians())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use super::*;
+
+    #[test]
+    fn domain_coloring_round_trips() {
+        let strategy = DomainColorStrategy::new(0.5);
+        for magnitude in [0.05, 0.5, 2.] {
+            for step in 0..12 {
+                let value =
+                    Complex32::from_polar(magnitude, TAU * step as f32 / 12.);
+                let decoded =
+                    strategy.color_to_value(&strategy.value_to_color(value));
+                assert!(
+                    (decoded - value).norm() < 0.05 * magnitude.max(0.5),
+                    "{} decodes to {}",
+                    value,
+                    decoded
+                );
+            }
+        }
+
+        // non-finite values are treated as silence
+        let color = strategy.value_to_color(Complex32::new(f32::NAN, 0.));
+        assert_eq!(color.0, [0, 0, 0, 255]);
+    }
+}
@@ -3,5 +3,8 @@
 pub mod color;
 pub use color::ColorStrategy;
 
+pub mod complex;
+pub use complex::ComplexColorStrategy;
+
 pub mod space;
 pub use space::SpaceStrategy;
@@ -1,9 +1,11 @@
 //! Mapping strategies between time and space.
 
+use std::{error, fmt};
+
 use hilbert::fast_hilbert::hilbert_axes;
 use num_bigint::BigUint;
 
-use crate::Coord;
+use crate::{splitmix64, Coord};
 
 /// A space strategy which represents a mapping between time and two-dimensional
 /// space.
@@ -18,6 +20,18 @@ pub trait SpaceStrategy<const N: usize> {
     /// If `dimension` exceeds `N`, then this function may panic.
     fn length(&self, dimension: usize) -> u32;
 
+    /// Call `f` with every pixel covered by the point at `index`.
+    ///
+    /// By default, each point covers exactly the pixel at its coordinate.
+    /// Strategies whose points do not map 1:1 to pixels should override this,
+    /// in which case [`SpaceStrategy::index_to_coord`] should give the pixel at
+    /// the center of the point.
+    fn for_each_pixel(&self, index: usize, f: &mut dyn FnMut(Coord<N>)) {
+        if let Some(coord) = self.index_to_coord(index) {
+            f(coord);
+        }
+    }
+
     /// The amount which this space contains.
     ///
     /// By default, this is equal to the product of the lengths of all of the
@@ -27,7 +41,31 @@ pub trait SpaceStrategy<const N: usize> {
     }
 }
 
+impl<const N: usize, S> SpaceStrategy<N> for Box<S>
+where
+    S: SpaceStrategy<N> + ?Sized,
+{
+    fn index_to_coord(&self, index: usize) -> Option<Coord<N>> {
+        (**self).index_to_coord(index)
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        (**self).length(dimension)
+    }
+
+    fn for_each_pixel(&self, index: usize, f: &mut dyn FnMut(Coord<N>)) {
+        (**self).for_each_pixel(index, f)
+    }
+
+    fn size(&self) -> usize {
+        (**self).size()
+    }
+}
+
 /// Adapt a space strategy of a lower dimension into a higher dimension.
+///
+/// The coordinates of the inner strategy are embedded into the first `A` axes,
+/// and the remaining axes have a length of one.
 pub struct SpaceStrategyAdapter<const A: usize, const B: usize, S>
 where
     S: SpaceStrategy<A>,
@@ -42,9 +80,13 @@ where
     pub fn new(inner: S) -> Self {
         assert!(A < B);
 
-        Self {
-            inner,
-        }
+        Self { inner }
+    }
+
+    fn embed(coord: Coord<A>) -> Coord<B> {
+        let mut to_coord = [0; B];
+        to_coord[..A].copy_from_slice(&coord);
+        to_coord
     }
 }
 
@@ -52,17 +94,9 @@ impl<const A: usize, const B: usize, S> SpaceStrategy<B>
     for SpaceStrategyAdapter<A, B, S>
 where
     S: SpaceStrategy<A>,
-    [u32; B]: Default,
 {
     fn index_to_coord(&self, index: usize) -> Option<Coord<B>> {
-        match self.inner.index_to_coord(index) {
-            Some(from_coord) => {
-                let mut to_coord: [u32; B] = Default::default();
-                to_coord.copy_from_slice(&from_coord);
-                Some(to_coord)
-            }
-            None => None,
-        }
+        self.inner.index_to_coord(index).map(Self::embed)
     }
 
     fn length(&self, dimension: usize) -> u32 {
@@ -74,6 +108,271 @@ where
             1
         }
     }
+
+    fn for_each_pixel(&self, index: usize, f: &mut dyn FnMut(Coord<B>)) {
+        self.inner
+            .for_each_pixel(index, &mut |coord| f(Self::embed(coord)))
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+}
+
+/// Slice a space strategy of a higher dimension down to a lower dimension by
+/// fixing some of its axes.
+///
+/// The points in the slice are visited in the same order as the inner strategy
+/// visits them, so the curve is only continuous where the inner curve stays
+/// within the slice. The indices of those points are found up front.
+pub struct SliceSpaceStrategy<const A: usize, const B: usize, S>
+where
+    S: SpaceStrategy<A>,
+{
+    inner: S,
+    fixed: [Option<u32>; A],
+    indices: Vec<usize>,
+}
+
+impl<const A: usize, const B: usize, S> SliceSpaceStrategy<A, B, S>
+where
+    S: SpaceStrategy<A>,
+{
+    /// Create a slice where each axis is either fixed at a position or kept,
+    /// if `None`. Exactly `B` axes must be kept.
+    pub fn new(inner: S, fixed: [Option<u32>; A]) -> Self {
+        assert!(fixed.iter().filter(|axis| axis.is_none()).count() == B);
+        for (dimension, position) in fixed.iter().enumerate() {
+            if let Some(position) = position {
+                assert!(*position < inner.length(dimension));
+            }
+        }
+
+        let mut slice = SliceSpaceStrategy {
+            inner,
+            fixed,
+            indices: Vec::new(),
+        };
+        slice.indices = (0..slice.inner.size())
+            .filter(|&index| {
+                slice
+                    .inner
+                    .index_to_coord(index)
+                    .and_then(|coord| slice.project(coord))
+                    .is_some()
+            })
+            .collect();
+
+        slice
+    }
+
+    /// The axes of the inner strategy which are kept, in order.
+    fn kept_axes(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..A).filter(|&dimension| self.fixed[dimension].is_none())
+    }
+
+    /// Project a coordinate onto the slice, if it lies within it.
+    fn project(&self, coord: Coord<A>) -> Option<Coord<B>> {
+        let in_slice =
+            self.fixed.iter().zip(coord).all(
+                |(position, value)| match position {
+                    Some(position) => *position == value,
+                    None => true,
+                },
+            );
+        if !in_slice {
+            return None;
+        }
+
+        let mut to_coord = [0; B];
+        for (value, dimension) in to_coord.iter_mut().zip(self.kept_axes()) {
+            *value = coord[dimension];
+        }
+        Some(to_coord)
+    }
+}
+
+impl<const A: usize, const B: usize, S> SpaceStrategy<B>
+    for SliceSpaceStrategy<A, B, S>
+where
+    S: SpaceStrategy<A>,
+{
+    fn index_to_coord(&self, index: usize) -> Option<Coord<B>> {
+        let coord = self.inner.index_to_coord(*self.indices.get(index)?)?;
+        self.project(coord)
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        let axis = self.kept_axes().nth(dimension).expect("invalid dimension");
+        self.inner.length(axis)
+    }
+
+    fn for_each_pixel(&self, index: usize, f: &mut dyn FnMut(Coord<B>)) {
+        if let Some(&index) = self.indices.get(index) {
+            self.inner.for_each_pixel(index, &mut |coord| {
+                if let Some(coord) = self.project(coord) {
+                    f(coord);
+                }
+            });
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.indices.len()
+    }
+}
+
+/// Permute the axes of a space strategy.
+///
+/// The *n*th axis of this strategy is the `axes[n]`th axis of the inner one, so
+/// `[1, 0]` transposes an image.
+pub struct TransposeSpaceStrategy<const N: usize, S>
+where
+    S: SpaceStrategy<N>,
+{
+    inner: S,
+    axes: [usize; N],
+}
+
+impl<const N: usize, S> TransposeSpaceStrategy<N, S>
+where
+    S: SpaceStrategy<N>,
+{
+    pub fn new(inner: S, axes: [usize; N]) -> Self {
+        let mut seen = [false; N];
+        for &axis in &axes {
+            assert!(axis < N && !seen[axis], "axes must be a permutation");
+            seen[axis] = true;
+        }
+
+        TransposeSpaceStrategy { inner, axes }
+    }
+
+    fn permute(&self, coord: Coord<N>) -> Coord<N> {
+        self.axes.map(|axis| coord[axis])
+    }
+}
+
+impl<const N: usize, S> SpaceStrategy<N> for TransposeSpaceStrategy<N, S>
+where
+    S: SpaceStrategy<N>,
+{
+    fn index_to_coord(&self, index: usize) -> Option<Coord<N>> {
+        self.inner
+            .index_to_coord(index)
+            .map(|coord| self.permute(coord))
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        self.inner.length(self.axes[dimension])
+    }
+
+    fn for_each_pixel(&self, index: usize, f: &mut dyn FnMut(Coord<N>)) {
+        self.inner
+            .for_each_pixel(index, &mut |coord| f(self.permute(coord)))
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+}
+
+/// A geometric transformation of a two-dimensional space.
+///
+/// The steps are applied in the order of the fields: first mirroring, then
+/// transposition, then rotation, and finally the offset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Transform {
+    /// Mirror left to right.
+    pub mirror_x: bool,
+    /// Mirror top to bottom.
+    pub mirror_y: bool,
+    /// Swap the axes.
+    pub transpose: bool,
+    /// The number of clockwise quarter turns.
+    pub quarter_turns: u32,
+    /// A shift of the coordinates, which wraps around the edges.
+    pub offset: [i32; 2],
+}
+
+/// A [`SpaceStrategy`] which applies a [`Transform`] to the coordinates of an
+/// inner strategy.
+///
+/// For example, mirroring a Hilbert curve left to right maps it onto itself in
+/// reverse, and rotating or mirroring moves the start of a curve to another
+/// corner.
+pub struct TransformedSpaceStrategy<S>
+where
+    S: SpaceStrategy<2>,
+{
+    inner: S,
+    transform: Transform,
+}
+
+impl<S> TransformedSpaceStrategy<S>
+where
+    S: SpaceStrategy<2>,
+{
+    pub fn new(inner: S, transform: Transform) -> Self {
+        TransformedSpaceStrategy { inner, transform }
+    }
+
+    /// Whether the axes of the inner strategy end up swapped.
+    fn swaps_axes(&self) -> bool {
+        self.transform.transpose ^ (self.transform.quarter_turns % 2 == 1)
+    }
+
+    fn apply(&self, [mut x, mut y]: Coord<2>) -> Coord<2> {
+        let (mut width, mut height) =
+            (self.inner.length(0), self.inner.length(1));
+
+        if self.transform.mirror_x {
+            x = width - 1 - x;
+        }
+        if self.transform.mirror_y {
+            y = height - 1 - y;
+        }
+        if self.transform.transpose {
+            (x, y, width, height) = (y, x, height, width);
+        }
+        for _ in 0..self.transform.quarter_turns % 4 {
+            (x, y, width, height) = (height - 1 - y, x, height, width);
+        }
+
+        let [dx, dy] = self.transform.offset;
+        [
+            (x as i64 + dx as i64).rem_euclid(width as i64) as u32,
+            (y as i64 + dy as i64).rem_euclid(height as i64) as u32,
+        ]
+    }
+}
+
+impl<S> SpaceStrategy<2> for TransformedSpaceStrategy<S>
+where
+    S: SpaceStrategy<2>,
+{
+    fn index_to_coord(&self, index: usize) -> Option<Coord<2>> {
+        self.inner
+            .index_to_coord(index)
+            .map(|coord| self.apply(coord))
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        match (dimension, self.swaps_axes()) {
+            (0, false) | (1, true) => self.inner.length(0),
+            (1, false) | (0, true) => self.inner.length(1),
+            _ => panic!("invalid dimension"),
+        }
+    }
+
+    fn for_each_pixel(&self, index: usize, f: &mut dyn FnMut(Coord<2>)) {
+        self.inner
+            .for_each_pixel(index, &mut |coord| f(self.apply(coord)))
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
 }
 
 pub struct HilbertSpaceStrategy {
@@ -106,6 +405,568 @@ impl SpaceStrategy<2> for HilbertSpaceStrategy {
     }
 }
 
+/// A [`SpaceStrategy`] which follows a three-dimensional Hilbert curve through
+/// a cube.
+pub struct HilbertVolumeSpaceStrategy {
+    size_exp: u32,
+}
+
+impl HilbertVolumeSpaceStrategy {
+    pub fn from_size(size: u32) -> Self {
+        HilbertVolumeSpaceStrategy {
+            size_exp: size.log2(),
+        }
+    }
+}
+
+impl SpaceStrategy<3> for HilbertVolumeSpaceStrategy {
+    fn index_to_coord(&self, index: usize) -> Option<Coord<3>> {
+        if index >= self.size() {
+            return None;
+        }
+
+        let coords = hilbert_axes(
+            &BigUint::from(index),
+            self.size_exp.max(1) as usize,
+            3,
+        );
+        Some([coords[0], coords[1], coords[2]])
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        assert!(dimension < 3);
+
+        2u32.pow(self.size_exp)
+    }
+
+    fn size(&self) -> usize {
+        2usize.pow(3 * self.size_exp)
+    }
+}
+
+/// A [`SpaceStrategy`] which follows a Moore curve, a closed-loop variant of
+/// the Hilbert curve.
+///
+/// The last point of the curve neighbors the first, so audio which loops from
+/// end to start also loops continuously in space.
+pub struct MooreSpaceStrategy {
+    size_exp: u32,
+}
+
+impl MooreSpaceStrategy {
+    pub fn from_size(size: u32) -> Self {
+        assert!(size >= 2 && size.is_power_of_two());
+
+        MooreSpaceStrategy {
+            size_exp: size.log2(),
+        }
+    }
+}
+
+impl SpaceStrategy<2> for MooreSpaceStrategy {
+    fn index_to_coord(&self, index: usize) -> Option<Coord<2>> {
+        if index >= self.size() {
+            return None;
+        }
+
+        // four Hilbert curves, one per quadrant, rotated so that they join up
+        // into a loop starting and ending at the top middle
+        let half = 2u32.pow(self.size_exp - 1);
+        let quadrant_size = (half * half) as usize;
+        let [x, y] = hilbert_index_to_coord(half, index % quadrant_size);
+
+        let coord = match index / quadrant_size {
+            0 => [half - 1 - y, x],
+            1 => [half - 1 - y, half + x],
+            2 => [half + y, 2 * half - 1 - x],
+            _ => [half + y, half - 1 - x],
+        };
+
+        Some(coord)
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        assert!(dimension < 2);
+
+        2u32.pow(self.size_exp)
+    }
+
+    fn size(&self) -> usize {
+        2u32.pow(2 * self.size_exp) as usize
+    }
+}
+
+/// Convert an index along a Hilbert curve of side `size` into a coordinate.
+///
+/// The curve starts at `(0, 0)` and ends at `(size - 1, 0)`.
+fn hilbert_index_to_coord(size: u32, index: usize) -> Coord<2> {
+    let (mut x, mut y) = (0, 0);
+    let mut index = index as u32;
+    let mut side = 1;
+
+    while side < size {
+        let rx = 1 & (index / 2);
+        let ry = 1 & (index ^ rx);
+
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        x += side * rx;
+        y += side * ry;
+        index /= 4;
+        side *= 2;
+    }
+
+    [x, y]
+}
+
+/// A [`SpaceStrategy`] which follows a Peano curve, whose sides are a power of
+/// three.
+pub struct PeanoSpaceStrategy {
+    order: u32,
+}
+
+impl PeanoSpaceStrategy {
+    pub fn from_size(size: u32) -> Self {
+        let order = (0..=20)
+            .find(|&order| 3u32.pow(order) == size)
+            .expect("size must be a power of three");
+
+        PeanoSpaceStrategy { order }
+    }
+}
+
+impl SpaceStrategy<2> for PeanoSpaceStrategy {
+    fn index_to_coord(&self, index: usize) -> Option<Coord<2>> {
+        if index >= self.size() {
+            return None;
+        }
+
+        // The index is read as base 3 digits a1 b1 a2 b2 ..., from which each
+        // digit of x and y is taken, flipped (d -> 2 - d) whenever the sum of
+        // the preceding digits of the other axis is odd.
+        let (mut x, mut y) = (0, 0);
+        let (mut a_sum, mut b_sum) = (0, 0);
+
+        for level in (0..self.order).rev() {
+            let pair = (index / 9usize.pow(level)) % 9;
+            let (a, b) = ((pair / 3) as u32, (pair % 3) as u32);
+
+            x = 3 * x + if b_sum % 2 == 0 { a } else { 2 - a };
+            a_sum += a;
+            y = 3 * y + if a_sum % 2 == 0 { b } else { 2 - b };
+            b_sum += b;
+        }
+
+        Some([x, y])
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        assert!(dimension < 2);
+
+        3u32.pow(self.order)
+    }
+
+    fn size(&self) -> usize {
+        9usize.pow(self.order)
+    }
+}
+
+/// A [`SpaceStrategy`] which follows a Gosper curve (or flowsnake) over a
+/// hexagonal lattice.
+///
+/// Each point of the curve is a hexagonal cell, rasterized in an offset layout
+/// where every odd row is shifted right by half a cell. A cell covers a block
+/// of `cell_size` pixels across, so [`SpaceStrategy::index_to_coord`] gives
+/// the pixel at the center of the cell.
+pub struct GosperSpaceStrategy {
+    cells: Vec<Coord<2>>,
+    cell_width: u32,
+    cell_height: u32,
+    columns: u32,
+    rows: u32,
+}
+
+impl GosperSpaceStrategy {
+    /// Create a new strategy for a curve of the given order, containing `7 ^
+    /// order` cells.
+    pub fn new(order: u32, cell_size: u32) -> Self {
+        assert!(cell_size >= 2);
+
+        // walk the curve in axial coordinates
+        let mut axial = vec![(0, 0)];
+        gosper_walk(true, order, &mut 0, &mut axial);
+        axial.truncate(7usize.pow(order));
+
+        // convert to offset coordinates where odd rows are shifted right
+        let min_r = axial.iter().map(|&(_, r)| r).min().unwrap();
+        let offset: Vec<_> = axial
+            .iter()
+            .map(|&(q, r)| {
+                let row = r - min_r;
+                (q + row / 2, row)
+            })
+            .collect();
+        let min_col = offset.iter().map(|&(col, _)| col).min().unwrap();
+        let cells: Vec<_> = offset
+            .iter()
+            .map(|&(col, row)| [(col - min_col) as u32, row as u32])
+            .collect();
+
+        let columns = cells.iter().map(|&[col, _]| col).max().unwrap() + 1;
+        let rows = cells.iter().map(|&[_, row]| row).max().unwrap() + 1;
+
+        // rows of a hexagonal lattice sit sqrt(3) / 2 of the cell width apart,
+        // since every other row is shifted by half a cell and nests into the
+        // gaps of the rows around it
+        let cell_height =
+            ((cell_size as f32 * 3f32.sqrt() / 2.).round() as u32).max(1);
+
+        GosperSpaceStrategy {
+            cells,
+            cell_width: cell_size,
+            cell_height,
+            columns,
+            rows,
+        }
+    }
+
+    /// The top-left pixel of the cell at `index`.
+    fn cell_origin(&self, index: usize) -> Option<Coord<2>> {
+        let [col, row] = *self.cells.get(index)?;
+        let shift = if row % 2 == 1 { self.cell_width / 2 } else { 0 };
+        Some([col * self.cell_width + shift, row * self.cell_height])
+    }
+}
+
+impl SpaceStrategy<2> for GosperSpaceStrategy {
+    fn index_to_coord(&self, index: usize) -> Option<Coord<2>> {
+        let [x, y] = self.cell_origin(index)?;
+        Some([x + self.cell_width / 2, y + self.cell_height / 2])
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        assert!(dimension < 2);
+
+        if dimension == 0 {
+            self.columns * self.cell_width + self.cell_width / 2
+        } else {
+            self.rows * self.cell_height
+        }
+    }
+
+    fn for_each_pixel(&self, index: usize, f: &mut dyn FnMut(Coord<2>)) {
+        if let Some([x0, y0]) = self.cell_origin(index) {
+            for y in y0..y0 + self.cell_height {
+                for x in x0..x0 + self.cell_width {
+                    f([x, y]);
+                }
+            }
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+/// Walk one symbol of the Gosper curve's L-system, appending every point
+/// reached to `points` in axial hexagonal coordinates.
+///
+/// The rules are `A -> A-B--B+A++AA+B-` and `B -> +A-BB--B-A++A+B`, where both
+/// symbols move forward one cell and `+`/`-` turn by 60 degrees.
+fn gosper_walk(
+    a: bool,
+    order: u32,
+    direction: &mut usize,
+    points: &mut Vec<(i32, i32)>,
+) {
+    const DIRECTIONS: [(i32, i32); 6] =
+        [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+
+    if order == 0 {
+        let (dq, dr) = DIRECTIONS[*direction];
+        let &(q, r) = points.last().unwrap();
+        points.push((q + dq, r + dr));
+        return;
+    }
+
+    let rule = if a {
+        "A-B--B+A++AA+B-"
+    } else {
+        "+A-BB--B-A++A+B"
+    };
+    for symbol in rule.chars() {
+        match symbol {
+            'A' => gosper_walk(true, order - 1, direction, points),
+            'B' => gosper_walk(false, order - 1, direction, points),
+            '+' => *direction = (*direction + 1) % 6,
+            _ => *direction = (*direction + 5) % 6,
+        }
+    }
+}
+
+/// A [`SpaceStrategy`] which follows a square spiral, starting at the center
+/// of the image and winding outward counterclockwise.
+///
+/// For images with an even side, the spiral starts from the 2x2 block of
+/// pixels at the center instead of a single pixel.
+pub struct SpiralSpaceStrategy {
+    size: u32,
+}
+
+impl SpiralSpaceStrategy {
+    pub fn from_size(size: u32) -> Self {
+        assert!(size > 0);
+
+        SpiralSpaceStrategy { size }
+    }
+}
+
+impl SpaceStrategy<2> for SpiralSpaceStrategy {
+    fn index_to_coord(&self, index: usize) -> Option<Coord<2>> {
+        if index >= self.size() {
+            return None;
+        }
+
+        let parity = self.size % 2;
+        if parity == 1 && index == 0 {
+            let center = self.size / 2;
+            return Some([center, center]);
+        }
+
+        // find the side of the smallest ring (of the same parity as the image)
+        // which contains the index
+        let mut side = (index as f64).sqrt() as u32;
+        while (side as usize).pow(2) > index {
+            side -= 1;
+        }
+        side += 1;
+        if side % 2 != parity {
+            side += 1;
+        }
+
+        // each ring starts just right of the bottom-right corner of the ring
+        // inside of it, and ends on its own bottom-right corner
+        let offset = index - (side as usize - 2).pow(2);
+        let (edge, pos) =
+            (offset as u32 / (side - 1), offset as u32 % (side - 1));
+        let (x0, y0) = ((self.size - side) / 2, (self.size - side) / 2);
+        let (x1, y1) = (x0 + side - 1, y0 + side - 1);
+
+        let coord = match edge {
+            0 => [x1, y1 - 1 - pos],
+            1 => [x1 - 1 - pos, y0],
+            2 => [x0, y0 + 1 + pos],
+            _ => [x0 + 1 + pos, y1],
+        };
+
+        Some(coord)
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        assert!(dimension < 2);
+
+        self.size
+    }
+}
+
+/// A [`SpaceStrategy`] which visits concentric rings around the center of the
+/// image, from the inside out.
+///
+/// Each ring holds the pixels whose distance from the center rounds to the
+/// same value, visited in order of their angle.
+pub struct RingSpaceStrategy {
+    size: u32,
+    coords: Vec<Coord<2>>,
+}
+
+impl RingSpaceStrategy {
+    pub fn from_size(size: u32) -> Self {
+        let center = (size as f32 - 1.) / 2.;
+        let polar = |[x, y]: Coord<2>| {
+            let (dx, dy) = (x as f32 - center, center - y as f32);
+            let angle = dy.atan2(dx).rem_euclid(std::f32::consts::TAU);
+            (dx.hypot(dy).round() as u32, angle)
+        };
+
+        let mut coords: Vec<_> = (0..size)
+            .flat_map(|y| (0..size).map(move |x| [x, y]))
+            .collect();
+        coords.sort_by(|&a, &b| {
+            let (a, b) = (polar(a), polar(b));
+            a.0.cmp(&b.0).then(a.1.total_cmp(&b.1))
+        });
+
+        RingSpaceStrategy { size, coords }
+    }
+}
+
+impl SpaceStrategy<2> for RingSpaceStrategy {
+    fn index_to_coord(&self, index: usize) -> Option<Coord<2>> {
+        self.coords.get(index).copied()
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        assert!(dimension < 2);
+
+        self.size
+    }
+}
+
+/// A [`SpaceStrategy`] which lays out the beats of a fixed tempo as Hilbert
+/// squares, tiled row by row.
+///
+/// Each beat fills exactly one Hilbert square whose side is a power of two, so
+/// audio must first be resampled to [`TempoSpaceStrategy::sample_rate`] for
+/// the beats to line up with the squares. Beats are grouped into square tiles
+/// of `beats_per_tile` beats each, which are then placed left to right and top
+/// to bottom.
+pub struct TempoSpaceStrategy {
+    bpm: f32,
+    channels: u16,
+    beat: HilbertSpaceStrategy,
+    tile: HilbertSpaceStrategy,
+    columns: u32,
+    rows: u32,
+}
+
+impl TempoSpaceStrategy {
+    /// Create a new strategy for audio at `bpm` beats per minute, originally
+    /// sampled at `sample_rate` with `channels` interleaved channels.
+    ///
+    /// The size of each beat is chosen to be the smallest power of four which
+    /// holds the original number of samples per beat across all channels, so
+    /// that audio is never resampled to a lower rate. `beats_per_tile` must be
+    /// a power of four so that every beat is a sub-square of its tile.
+    pub fn new(
+        bpm: f32,
+        sample_rate: u32,
+        channels: u16,
+        beats_per_tile: u32,
+        columns: u32,
+        rows: u32,
+    ) -> Result<Self, TempoError> {
+        if !(bpm > 0. && bpm.is_finite()) {
+            return Err(TempoError::Bpm(bpm));
+        }
+        if sample_rate == 0 {
+            return Err(TempoError::SampleRate);
+        }
+        if channels == 0 {
+            return Err(TempoError::Channels);
+        }
+        if !(beats_per_tile.is_power_of_two()
+            && beats_per_tile & 0x5555_5555 != 0)
+        {
+            return Err(TempoError::BeatsPerTile(beats_per_tile));
+        }
+
+        let samples_per_beat =
+            sample_rate as f64 * 60. / bpm as f64 * channels as f64;
+        let beat_exp = (samples_per_beat.log2() / 2.).ceil().max(0.) as u32;
+        let tile_exp = beat_exp + beats_per_tile.log2() / 2;
+        if tile_exp >= 16 {
+            return Err(TempoError::Bpm(bpm));
+        }
+
+        Ok(TempoSpaceStrategy {
+            bpm,
+            channels,
+            beat: HilbertSpaceStrategy::from_size(2u32.pow(beat_exp)),
+            tile: HilbertSpaceStrategy::from_size(2u32.pow(tile_exp)),
+            columns,
+            rows,
+        })
+    }
+
+    /// The exact sample rate at which each beat fills one Hilbert square.
+    ///
+    /// This is not a whole number of hertz unless the tempo divides the size
+    /// of a beat evenly, so audio must be resampled by the exact ratio for the
+    /// beats not to drift from the squares.
+    pub fn sample_rate(&self) -> f64 {
+        self.beat.size() as f64 / self.channels as f64 * self.bpm as f64 / 60.
+    }
+
+    /// The number of samples in a single beat, across all channels.
+    pub fn beat_size(&self) -> usize {
+        self.beat.size()
+    }
+}
+
+/// An error returned when a [`TempoSpaceStrategy`] cannot lay out the beats
+/// it is given.
+#[derive(Debug)]
+pub enum TempoError {
+    /// The tempo is not a positive number of beats per minute, or is too slow
+    /// for a tile to fit in an image.
+    Bpm(f32),
+    /// The original sample rate is zero.
+    SampleRate,
+    /// The audio has no channels.
+    Channels,
+    /// The number of beats per tile is not a power of four.
+    BeatsPerTile(u32),
+}
+
+impl fmt::Display for TempoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TempoError::Bpm(bpm) => write!(
+                f,
+                "tempo must be a positive number of bpm which fits a tile in \
+                 an image, not {}",
+                bpm
+            ),
+            TempoError::SampleRate => write!(f, "sample rate must not be zero"),
+            TempoError::Channels => {
+                write!(f, "audio must have at least one channel")
+            }
+            TempoError::BeatsPerTile(beats) => write!(
+                f,
+                "beats per tile must be a power of four, not {}",
+                beats
+            ),
+        }
+    }
+}
+
+impl error::Error for TempoError {}
+
+impl SpaceStrategy<2> for TempoSpaceStrategy {
+    fn index_to_coord(&self, index: usize) -> Option<Coord<2>> {
+        if index >= self.size() {
+            return None;
+        }
+
+        let tile = (index / self.tile.size()) as u32;
+        let [x, y] = self.tile.index_to_coord(index % self.tile.size())?;
+        let side = self.tile.length(0);
+
+        Some([
+            (tile % self.columns) * side + x,
+            (tile / self.columns) * side + y,
+        ])
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        assert!(dimension < 2);
+
+        let tiles = if dimension == 0 {
+            self.columns
+        } else {
+            self.rows
+        };
+        tiles * self.tile.length(dimension)
+    }
+}
+
 pub struct LineSpaceStrategy {
     length: usize,
 }
@@ -131,3 +992,558 @@ impl SpaceStrategy<1> for LineSpaceStrategy {
         self.length
     }
 }
+
+/// A [`SpaceStrategy`] which visits an image row by row.
+pub struct RasterSpaceStrategy {
+    width: u32,
+    height: u32,
+}
+
+impl RasterSpaceStrategy {
+    pub fn new(width: u32, height: u32) -> Self {
+        RasterSpaceStrategy { width, height }
+    }
+}
+
+impl SpaceStrategy<2> for RasterSpaceStrategy {
+    fn index_to_coord(&self, index: usize) -> Option<Coord<2>> {
+        if index >= self.size() {
+            return None;
+        }
+
+        let width = self.width as usize;
+        Some([(index % width) as u32, (index / width) as u32])
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        match dimension {
+            0 => self.width,
+            1 => self.height,
+            _ => panic!("invalid dimension"),
+        }
+    }
+}
+
+/// A [`SpaceStrategy`] which lays out tiles with an outer strategy, and fills
+/// each tile with an inner strategy.
+///
+/// Each point of the outer strategy is a tile the size of the inner strategy,
+/// which holds a contiguous run of `inner.size()` samples. For example, a
+/// raster of Hilbert tiles keeps locality within each tile while reading like
+/// a grid of clips, and a Hilbert curve of raster tiles does the opposite.
+pub struct TiledSpaceStrategy<O, I>
+where
+    O: SpaceStrategy<2>,
+    I: SpaceStrategy<2>,
+{
+    outer: O,
+    inner: I,
+}
+
+impl<O, I> TiledSpaceStrategy<O, I>
+where
+    O: SpaceStrategy<2>,
+    I: SpaceStrategy<2>,
+{
+    pub fn new(outer: O, inner: I) -> Self {
+        assert!(inner.size() > 0);
+
+        TiledSpaceStrategy { outer, inner }
+    }
+
+    /// The tile holding `index` and the index within that tile.
+    fn split(&self, index: usize) -> (usize, usize) {
+        (index / self.inner.size(), index % self.inner.size())
+    }
+
+    fn combine(&self, [tx, ty]: Coord<2>, [x, y]: Coord<2>) -> Coord<2> {
+        [tx * self.inner.length(0) + x, ty * self.inner.length(1) + y]
+    }
+}
+
+impl<O, I> SpaceStrategy<2> for TiledSpaceStrategy<O, I>
+where
+    O: SpaceStrategy<2>,
+    I: SpaceStrategy<2>,
+{
+    fn index_to_coord(&self, index: usize) -> Option<Coord<2>> {
+        let (tile, index) = self.split(index);
+        let tile = self.outer.index_to_coord(tile)?;
+        let coord = self.inner.index_to_coord(index)?;
+        Some(self.combine(tile, coord))
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        self.outer.length(dimension) * self.inner.length(dimension)
+    }
+
+    fn for_each_pixel(&self, index: usize, f: &mut dyn FnMut(Coord<2>)) {
+        let (tile, index) = self.split(index);
+        self.outer.for_each_pixel(tile, &mut |tile| {
+            self.inner.for_each_pixel(index, &mut |coord| {
+                f(self.combine(tile, coord))
+            })
+        })
+    }
+
+    fn size(&self) -> usize {
+        self.outer.size() * self.inner.size()
+    }
+}
+
+/// The number of rounds used by [`PermutedSpaceStrategy`].
+const FEISTEL_ROUNDS: usize = 4;
+
+/// A [`SpaceStrategy`] which visits the space of an inner strategy in a
+/// pseudo-random order determined by a seed.
+///
+/// The order is a bijective permutation of the indices, computed on the fly by
+/// a small Feistel network, so no table needs to be stored. Indices which fall
+/// outside of the inner strategy's size are cycled through the network again
+/// until they land inside of it.
+pub struct PermutedSpaceStrategy<const N: usize, S>
+where
+    S: SpaceStrategy<N>,
+{
+    inner: S,
+    half_bits: u32,
+    keys: [u64; FEISTEL_ROUNDS],
+}
+
+impl<const N: usize, S> PermutedSpaceStrategy<N, S>
+where
+    S: SpaceStrategy<N>,
+{
+    pub fn new(inner: S, seed: u64) -> Self {
+        let bits = usize::BITS - inner.size().saturating_sub(1).leading_zeros();
+        let half_bits = bits.div_ceil(2).max(1);
+
+        let mut state = seed;
+        let keys = [(); FEISTEL_ROUNDS].map(|_| splitmix64(&mut state));
+
+        PermutedSpaceStrategy {
+            inner,
+            half_bits,
+            keys,
+        }
+    }
+
+    /// Map an index to its permuted index.
+    fn permute(&self, index: usize) -> usize {
+        let mut index = index as u64;
+        loop {
+            index = self.feistel(index);
+            if (index as usize) < self.inner.size() {
+                return index as usize;
+            }
+        }
+    }
+
+    /// A single pass through the Feistel network, which is a permutation of
+    /// all values with twice `half_bits` bits.
+    fn feistel(&self, value: u64) -> u64 {
+        let mask = (1 << self.half_bits) - 1;
+        let (mut left, mut right) = (value >> self.half_bits, value & mask);
+
+        for key in self.keys {
+            let mut state = right ^ key;
+            let round = splitmix64(&mut state) & mask;
+            (left, right) = (right, left ^ round);
+        }
+
+        (left << self.half_bits) | right
+    }
+}
+
+impl<const N: usize, S> SpaceStrategy<N> for PermutedSpaceStrategy<N, S>
+where
+    S: SpaceStrategy<N>,
+{
+    fn index_to_coord(&self, index: usize) -> Option<Coord<N>> {
+        if index >= self.size() {
+            return None;
+        }
+
+        self.inner.index_to_coord(self.permute(index))
+    }
+
+    fn for_each_pixel(&self, index: usize, f: &mut dyn FnMut(Coord<N>)) {
+        if index < self.size() {
+            self.inner.for_each_pixel(self.permute(index), f)
+        }
+    }
+
+    fn length(&self, dimension: usize) -> u32 {
+        self.inner.length(dimension)
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// Check that every index maps to a distinct point within the lengths of
+    /// the space, and that together they cover all of it.
+    fn assert_covers_once<const N: usize>(strategy: &impl SpaceStrategy<N>) {
+        let mut seen = HashSet::new();
+        for index in 0..strategy.size() {
+            let coord = strategy
+                .index_to_coord(index)
+                .unwrap_or_else(|| panic!("index {} has no coordinate", index));
+            for (dimension, &position) in coord.iter().enumerate() {
+                assert!(
+                    position < strategy.length(dimension),
+                    "{:?} is out of bounds",
+                    coord
+                );
+            }
+            assert!(seen.insert(coord), "{:?} is visited twice", coord);
+        }
+
+        let volume = (0..N)
+            .map(|dimension| strategy.length(dimension) as usize)
+            .product::<usize>();
+        assert_eq!(seen.len(), volume);
+    }
+
+    /// Check that every point neighbors the one before it.
+    fn assert_continuous<const N: usize>(strategy: &impl SpaceStrategy<N>) {
+        let coords = (0..strategy.size())
+            .map(|index| strategy.index_to_coord(index).unwrap())
+            .collect::<Vec<_>>();
+        for pair in coords.windows(2) {
+            assert!(
+                distance(pair[0], pair[1]) == 1,
+                "{:?} does not neighbor {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    fn distance<const N: usize>(a: Coord<N>, b: Coord<N>) -> u32 {
+        a.iter().zip(&b).map(|(&a, &b)| a.abs_diff(b)).sum()
+    }
+
+    #[test]
+    fn tempo_beats_fill_whole_squares() {
+        // 120 bpm of stereo audio at 4 kHz is 4000 samples per beat
+        let strategy = TempoSpaceStrategy::new(120., 4000, 2, 4, 3, 2).unwrap();
+        assert_eq!(strategy.beat_size(), 64 * 64);
+        assert_eq!(strategy.sample_rate(), 4096.);
+        assert_eq!(strategy.length(0), 3 * 128);
+        assert_eq!(strategy.length(1), 2 * 128);
+        assert_covers_once(&strategy);
+
+        // each beat is a contiguous square of its own
+        let beat = strategy.beat_size();
+        for start in (0..strategy.size()).step_by(beat) {
+            let coords = (start..start + beat)
+                .map(|index| strategy.index_to_coord(index).unwrap())
+                .collect::<Vec<_>>();
+            let [x0, y0] = coords[0].map(|position| position / 64 * 64);
+            assert!(coords.iter().all(|&[x, y]| x - x0 < 64 && y - y0 < 64));
+        }
+    }
+
+    #[test]
+    fn tempo_never_downsamples() {
+        // 57600 samples per beat, which is just over the square of 128
+        let strategy =
+            TempoSpaceStrategy::new(100., 48000, 2, 1, 1, 1).unwrap();
+        assert_eq!(strategy.beat_size(), 256 * 256);
+        let beats = strategy.sample_rate() * 2. * 60. / 100.;
+        assert!((beats - 65536.).abs() < 1e-6);
+
+        // exact powers of four are kept as they are
+        let strategy = TempoSpaceStrategy::new(60., 4096, 1, 1, 1, 1).unwrap();
+        assert_eq!(strategy.sample_rate(), 4096.);
+    }
+
+    #[test]
+    fn invalid_tempos_are_errors() {
+        assert!(TempoSpaceStrategy::new(0., 48000, 2, 4, 1, 1).is_err());
+        assert!(TempoSpaceStrategy::new(f32::NAN, 48000, 2, 4, 1, 1).is_err());
+        assert!(TempoSpaceStrategy::new(0.01, 48000, 2, 4, 1, 1).is_err());
+        assert!(TempoSpaceStrategy::new(120., 0, 2, 4, 1, 1).is_err());
+        assert!(TempoSpaceStrategy::new(120., 48000, 0, 4, 1, 1).is_err());
+        assert!(TempoSpaceStrategy::new(120., 48000, 2, 8, 1, 1).is_err());
+    }
+
+    #[test]
+    fn feistel_is_a_bijection() {
+        // sizes which are and are not a power of four, to cover cycle walking
+        for size in [1, 4, 16, 37, 1000] {
+            for seed in [0, 1, 0xdead_beef] {
+                let inner = LineSpaceStrategy::new(size);
+                let strategy = PermutedSpaceStrategy::new(inner, seed);
+                let mut indices = (0..strategy.size())
+                    .map(|index| strategy.permute(index))
+                    .collect::<Vec<_>>();
+                indices.sort_unstable();
+                assert!(indices.into_iter().eq(0..size));
+            }
+        }
+
+        let strategy =
+            PermutedSpaceStrategy::new(HilbertSpaceStrategy::from_size(16), 5);
+        assert_covers_once(&strategy);
+        assert!(strategy.index_to_coord(strategy.size()).is_none());
+    }
+
+    #[test]
+    fn feistel_depends_on_the_seed() {
+        let order = |seed| {
+            let inner = LineSpaceStrategy::new(64);
+            let strategy = PermutedSpaceStrategy::new(inner, seed);
+            (0..64)
+                .map(|index| strategy.permute(index))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(order(1), order(1));
+        assert_ne!(order(1), order(2));
+        assert_ne!(order(1), (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn moore_is_a_closed_loop() {
+        for size in [2, 4, 32] {
+            let strategy = MooreSpaceStrategy::from_size(size);
+            assert_covers_once(&strategy);
+            assert_continuous(&strategy);
+
+            let first = strategy.index_to_coord(0).unwrap();
+            let last = strategy.index_to_coord(strategy.size() - 1).unwrap();
+            assert_eq!(distance(first, last), 1);
+            assert!(strategy.index_to_coord(strategy.size()).is_none());
+        }
+    }
+
+    #[test]
+    fn peano_is_continuous() {
+        for size in [1, 3, 9, 27] {
+            let strategy = PeanoSpaceStrategy::from_size(size);
+            assert_covers_once(&strategy);
+            assert_continuous(&strategy);
+            assert_eq!(strategy.index_to_coord(0), Some([0, 0]));
+            assert!(strategy.index_to_coord(strategy.size()).is_none());
+        }
+    }
+
+    #[test]
+    fn gosper_cells_neighbor_without_overlapping() {
+        for (order, cell_size) in [(0, 2), (1, 4), (3, 6)] {
+            let strategy = GosperSpaceStrategy::new(order, cell_size);
+            assert_eq!(strategy.size(), 7usize.pow(order));
+
+            // back to axial coordinates, where every step is one of six
+            let axial = strategy
+                .cells
+                .iter()
+                .map(|&[col, row]| (col as i32 - row as i32 / 2, row as i32))
+                .collect::<Vec<_>>();
+            for pair in axial.windows(2) {
+                let step = (pair[1].0 - pair[0].0, pair[1].1 - pair[0].1);
+                assert!(
+                    [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)]
+                        .contains(&step),
+                    "{:?} does not neighbor {:?}",
+                    pair[0],
+                    pair[1]
+                );
+            }
+
+            let mut pixels = HashSet::new();
+            for index in 0..strategy.size() {
+                let mut block = HashSet::new();
+                strategy.for_each_pixel(index, &mut |coord| {
+                    assert!(coord[0] < strategy.length(0));
+                    assert!(coord[1] < strategy.length(1));
+                    block.insert(coord);
+                });
+                let center = strategy.index_to_coord(index).unwrap();
+                assert!(block.contains(&center));
+                assert!(pixels.is_disjoint(&block), "cell {} overlaps", index);
+                pixels.extend(block);
+            }
+            let cell_pixels = strategy.cell_width * strategy.cell_height;
+            assert_eq!(pixels.len(), strategy.size() * cell_pixels as usize);
+        }
+    }
+
+    #[test]
+    fn spiral_winds_outward_from_the_center() {
+        for size in [1, 2, 5, 8, 31] {
+            let strategy = SpiralSpaceStrategy::from_size(size);
+            assert_covers_once(&strategy);
+            assert_continuous(&strategy);
+
+            let [x, y] = strategy.index_to_coord(0).unwrap();
+            assert!(x.abs_diff(size / 2) <= 1 && y.abs_diff(size / 2) <= 1);
+            assert_eq!(
+                strategy.index_to_coord(strategy.size() - 1),
+                Some([size - 1, size - 1])
+            );
+            assert!(strategy.index_to_coord(strategy.size()).is_none());
+        }
+    }
+
+    #[test]
+    fn rings_grow_outward() {
+        for size in [1, 2, 7, 16] {
+            let strategy = RingSpaceStrategy::from_size(size);
+            assert_covers_once(&strategy);
+
+            let center = (size as f32 - 1.) / 2.;
+            let radii = (0..strategy.size())
+                .map(|index| {
+                    let [x, y] = strategy.index_to_coord(index).unwrap();
+                    let (dx, dy) = (x as f32 - center, y as f32 - center);
+                    dx.hypot(dy).round() as u32
+                })
+                .collect::<Vec<_>>();
+            assert!(radii.windows(2).all(|pair| pair[0] <= pair[1]));
+        }
+    }
+
+    #[test]
+    fn hilbert_volume_is_continuous() {
+        for size in [2, 4, 16] {
+            let strategy = HilbertVolumeSpaceStrategy::from_size(size);
+            assert_eq!(strategy.size(), (size * size * size) as usize);
+            assert_covers_once(&strategy);
+            assert_continuous(&strategy);
+            assert!(strategy.index_to_coord(strategy.size()).is_none());
+        }
+    }
+
+    #[test]
+    fn slices_cover_their_plane_once() {
+        for fixed in [[None, None, Some(1)], [Some(3), None, None]] {
+            let strategy = SliceSpaceStrategy::<3, 2, _>::new(
+                HilbertVolumeSpaceStrategy::from_size(4),
+                fixed,
+            );
+            assert_eq!(strategy.size(), 16);
+            assert_covers_once(&strategy);
+            assert!(strategy.index_to_coord(16).is_none());
+        }
+    }
+
+    #[test]
+    fn transposing_swaps_the_axes() {
+        let inner = RasterSpaceStrategy::new(3, 2);
+        let strategy = TransposeSpaceStrategy::new(inner, [1, 0]);
+        assert_eq!([strategy.length(0), strategy.length(1)], [2, 3]);
+        assert_covers_once(&strategy);
+        for index in 0..6 {
+            let [x, y] = strategy.index_to_coord(index).unwrap();
+            assert_eq!(index as u32, x * 3 + y);
+        }
+    }
+
+    #[test]
+    fn adapters_embed_into_more_axes() {
+        let strategy = SpaceStrategyAdapter::<2, 3, _>::new(
+            HilbertSpaceStrategy::from_size(4),
+        );
+        assert_eq!(
+            (0..3).map(|d| strategy.length(d)).collect::<Vec<_>>(),
+            [4, 4, 1]
+        );
+        assert_covers_once(&strategy);
+    }
+
+    #[test]
+    fn transforms_stay_within_the_image() {
+        for bits in 0..8 {
+            for quarter_turns in 0..4 {
+                let transform = Transform {
+                    mirror_x: bits & 1 != 0,
+                    mirror_y: bits & 2 != 0,
+                    transpose: bits & 4 != 0,
+                    quarter_turns,
+                    offset: [-4, 7],
+                };
+                let inner = RasterSpaceStrategy::new(3, 2);
+                let strategy = TransformedSpaceStrategy::new(inner, transform);
+                assert_covers_once(&strategy);
+            }
+        }
+    }
+
+    #[test]
+    fn transforms_move_the_start() {
+        let start = |transform| {
+            let inner = RasterSpaceStrategy::new(3, 2);
+            TransformedSpaceStrategy::new(inner, transform)
+                .index_to_coord(0)
+                .unwrap()
+        };
+
+        // a clockwise quarter turn moves the top left corner to the top right
+        let turn = Transform {
+            quarter_turns: 1,
+            ..Transform::default()
+        };
+        assert_eq!(start(turn), [1, 0]);
+
+        let mirror = Transform {
+            mirror_x: true,
+            mirror_y: true,
+            ..Transform::default()
+        };
+        assert_eq!(start(mirror), [2, 1]);
+
+        let offset = Transform {
+            offset: [-1, 3],
+            ..Transform::default()
+        };
+        assert_eq!(start(offset), [2, 1]);
+    }
+
+    #[test]
+    fn raster_reads_row_by_row() {
+        let strategy = RasterSpaceStrategy::new(5, 3);
+        assert_covers_once(&strategy);
+        assert_eq!(strategy.index_to_coord(7), Some([2, 1]));
+        assert!(strategy.index_to_coord(15).is_none());
+    }
+
+    #[test]
+    fn tiles_hold_contiguous_runs() {
+        let strategy = TiledSpaceStrategy::new(
+            RasterSpaceStrategy::new(3, 2),
+            HilbertSpaceStrategy::from_size(4),
+        );
+        assert_eq!([strategy.length(0), strategy.length(1)], [12, 8]);
+        assert_covers_once(&strategy);
+
+        for tile in 0..6 {
+            let (tx, ty) = (tile % 3 * 4, tile / 3 * 4);
+            for index in tile as usize * 16..(tile as usize + 1) * 16 {
+                let [x, y] = strategy.index_to_coord(index).unwrap();
+                assert!(x - tx < 4 && y - ty < 4);
+            }
+        }
+        assert!(strategy.index_to_coord(strategy.size()).is_none());
+
+        // tiles of cells with several pixels each keep them together
+        let strategy = TiledSpaceStrategy::new(
+            RasterSpaceStrategy::new(2, 1),
+            GosperSpaceStrategy::new(1, 2),
+        );
+        let mut pixels = HashSet::new();
+        for index in 0..strategy.size() {
+            strategy.for_each_pixel(index, &mut |coord| {
+                assert!(pixels.insert(coord), "{:?} is covered twice", coord);
+            });
+        }
+    }
+}
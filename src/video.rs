@@ -0,0 +1,200 @@
+//! Rendering of video frames which follow the playback of an encoded image.
+
+use nannou::image::{self, RgbaImage};
+
+use crate::strategy::SpaceStrategy;
+
+/// The color of the pixels played during the current frame.
+const PLAYHEAD_COLOR: image::Rgba<u8> = image::Rgba([255, 255, 255, 255]);
+
+/// How pixels which have not been played yet are shown.
+#[derive(Clone, Copy, Debug)]
+pub enum FrameStyle {
+    /// Pixels are hidden until they have been played.
+    Reveal,
+    /// Pixels are dimmed until they have been played.
+    Highlight,
+}
+
+/// An iterator over the video frames of an image being played back.
+///
+/// Each frame shows the pixels played so far, with the pixels played during
+/// the frame itself drawn as a playhead. Frame boundaries are computed exactly
+/// from the frame number rather than accumulated, so the frames never drift
+/// from the audio regardless of how the frame rate divides the sample rate.
+pub struct FrameRenderer {
+    image: RgbaImage,
+    canvas: RgbaImage,
+    space_strategy: Box<dyn SpaceStrategy<2> + Send>,
+    channels: u64,
+    samples_per_pixel: u64,
+    length: Option<u64>,
+    sample_rate: u64,
+    fps: u64,
+    frame: u64,
+}
+
+impl FrameRenderer {
+    pub fn new(
+        image: RgbaImage,
+        space_strategy: Box<dyn SpaceStrategy<2> + Send>,
+        channels: u16,
+        sample_rate: u32,
+        fps: u32,
+        style: FrameStyle,
+    ) -> Self {
+        assert!(image.width() == space_strategy.length(0));
+        assert!(image.height() == space_strategy.length(1));
+        assert!(channels > 0 && sample_rate > 0 && fps > 0);
+
+        let canvas = match style {
+            FrameStyle::Reveal => RgbaImage::from_pixel(
+                image.width(),
+                image.height(),
+                image::Rgba([0, 0, 0, 255]),
+            ),
+            FrameStyle::Highlight => {
+                let mut canvas = image.clone();
+                for pixel in canvas.pixels_mut() {
+                    let [r, g, b, a] = pixel.0;
+                    *pixel = image::Rgba([r / 4, g / 4, b / 4, a]);
+                }
+                canvas
+            }
+        };
+
+        FrameRenderer {
+            image,
+            canvas,
+            space_strategy,
+            channels: channels as u64,
+            samples_per_pixel: 1,
+            length: None,
+            sample_rate: sample_rate as u64,
+            fps: fps as u64,
+            frame: 0,
+        }
+    }
+
+    /// Play `samples_per_pixel` samples for each pixel, as packed by the color
+    /// strategy of the image.
+    pub fn with_samples_per_pixel(mut self, samples_per_pixel: usize) -> Self {
+        assert!(samples_per_pixel > 0);
+        self.samples_per_pixel = samples_per_pixel as u64;
+        self
+    }
+
+    /// Stop after `length` samples, such as the number recorded when encoding,
+    /// rather than at the end of the image.
+    pub fn with_length(mut self, length: usize) -> Self {
+        self.length = Some(length as u64);
+        self
+    }
+
+    /// The width and height of each frame.
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+
+    /// The number of frames per second.
+    pub fn fps(&self) -> u32 {
+        self.fps as u32
+    }
+
+    /// The total number of frames needed to play the entire image.
+    pub fn frame_count(&self) -> u64 {
+        let mut samples =
+            self.space_strategy.size() as u64 * self.samples_per_pixel;
+        if let Some(length) = self.length {
+            samples = samples.min(length);
+        }
+        let sample_frames = samples.div_ceil(self.channels);
+        (sample_frames * self.fps).div_ceil(self.sample_rate)
+    }
+
+    /// The index of the first pixel played during `frame`.
+    fn frame_start(&self, frame: u64) -> usize {
+        let sample_frame = frame * self.sample_rate / self.fps;
+        let mut pixels = self.space_strategy.size() as u64;
+        if let Some(length) = self.length {
+            pixels = pixels.min(length.div_ceil(self.samples_per_pixel));
+        }
+        (sample_frame * self.channels / self.samples_per_pixel).min(pixels)
+            as usize
+    }
+}
+
+impl Iterator for FrameRenderer {
+    type Item = RgbaImage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frame >= self.frame_count() {
+            return None;
+        }
+
+        let start = self.frame_start(self.frame);
+        let end = self.frame_start(self.frame + 1);
+        self.frame += 1;
+
+        let mut frame = self.canvas.clone();
+        for index in start..end {
+            self.space_strategy.for_each_pixel(index, &mut |[x, y]| {
+                self.canvas.put_pixel(x, y, *self.image.get_pixel(x, y));
+                frame.put_pixel(x, y, PLAYHEAD_COLOR);
+            });
+        }
+
+        Some(frame)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.frame_count() - self.frame) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::space::RasterSpaceStrategy;
+
+    fn renderer(style: FrameStyle) -> FrameRenderer {
+        // two frames of stereo audio per pixel row, at two frames per second
+        let image = RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255]));
+        FrameRenderer::new(
+            image,
+            Box::new(RasterSpaceStrategy::new(4, 4)),
+            2,
+            8,
+            2,
+            style,
+        )
+    }
+
+    fn count(frame: &RgbaImage, color: [u8; 4]) -> usize {
+        frame.pixels().filter(|pixel| pixel.0 == color).count()
+    }
+
+    #[test]
+    fn frames_follow_the_playhead() {
+        let frames = renderer(FrameStyle::Reveal).collect::<Vec<_>>();
+        assert_eq!(frames.len(), 2);
+
+        // every frame plays four frames of stereo audio, or eight pixels
+        assert_eq!(count(&frames[0], PLAYHEAD_COLOR.0), 8);
+        assert_eq!(count(&frames[0], [0, 0, 0, 255]), 8);
+        assert_eq!(count(&frames[1], PLAYHEAD_COLOR.0), 8);
+        assert_eq!(count(&frames[1], [255, 0, 0, 255]), 8);
+    }
+
+    #[test]
+    fn frames_stop_at_the_length() {
+        let frames = renderer(FrameStyle::Highlight)
+            .with_length(10)
+            .collect::<Vec<_>>();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(count(&frames[1], PLAYHEAD_COLOR.0), 2);
+        assert_eq!(count(&frames[1], [255, 0, 0, 255]), 8);
+        assert_eq!(count(&frames[1], [63, 0, 0, 255]), 6);
+    }
+}